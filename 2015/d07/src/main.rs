@@ -1,4 +1,7 @@
-use std::{collections::HashMap, env, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+};
 
 use Instruction::*;
 use Signal::*;
@@ -59,59 +62,214 @@ fn parse(input: &str) -> HashMap<String, Instruction> {
         .collect()
 }
 
-fn resolve(
-    wire: &str,
-    connections: &HashMap<String, Instruction>,
-    resolved_map: &mut HashMap<String, usize>,
-) -> usize {
-    if let Some(val) = resolved_map.get(wire) {
-        return *val;
+impl Instruction {
+    fn inputs(&self) -> Vec<&str> {
+        match self {
+            Provide(Literal(_)) => vec![],
+            Provide(Wire(a)) | And(Literal(_), a) | Not(a) | Lshift(a, _) | Rshift(a, _) => {
+                vec![a]
+            }
+            // A gate can use the same wire twice, but only depends on it once.
+            And(Wire(a), b) | OR(a, b) if a == b => vec![a],
+            And(Wire(a), b) | OR(a, b) => vec![a, b],
+        }
     }
 
-    let wire = wire.to_string();
+    fn label(&self) -> String {
+        match self {
+            Provide(Literal(val)) => val.to_string(),
+            Provide(Wire(_)) => "".to_string(),
+            And(Literal(val), _) => format!("{val} AND"),
+            And(Wire(_), _) => "AND".to_string(),
+            OR(_, _) => "OR".to_string(),
+            Not(_) => "NOT".to_string(),
+            Lshift(_, n) => format!("LSHIFT {n}"),
+            Rshift(_, n) => format!("RSHIFT {n}"),
+        }
+    }
+}
+
+struct Circuit {
+    connections: HashMap<String, Instruction>,
+    // All wires, such that every wire comes after the wires it depends on.
+    order: Vec<String>,
+    // The wires that directly depend on a given wire.
+    dependents: HashMap<String, HashSet<String>>,
+    values: HashMap<String, u16>,
+}
+
+impl Circuit {
+    fn new(connections: HashMap<String, Instruction>) -> Result<Circuit, String> {
+        let mut dependents: HashMap<String, HashSet<String>> = HashMap::new();
+
+        for (wire, instruction) in &connections {
+            for input in instruction.inputs() {
+                if !connections.contains_key(input) {
+                    return Err(format!("Wire {input} feeds {wire} but is never defined"));
+                }
+
+                dependents
+                    .entry(input.to_string())
+                    .or_default()
+                    .insert(wire.clone());
+            }
+        }
+
+        // Kahn's algorithm. Whatever is left without being ordered must be on
+        // (or downstream of) a cycle.
+        let mut n_inputs: HashMap<&String, usize> = connections
+            .iter()
+            .map(|(wire, instruction)| (wire, instruction.inputs().len()))
+            .collect();
+
+        let mut ready: Vec<&String> = n_inputs
+            .iter()
+            .filter(|(_, n)| **n == 0)
+            .map(|(wire, _)| *wire)
+            .collect();
+        ready.sort();
+
+        let mut order = Vec::new();
+
+        while let Some(wire) = ready.pop() {
+            order.push(wire.clone());
+
+            for dependent in dependents.get(wire).into_iter().flatten() {
+                let n = n_inputs.get_mut(dependent).unwrap();
+                *n -= 1;
 
-    let instruction = connections.get(&wire).unwrap();
+                if *n == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+
+        if order.len() < connections.len() {
+            // What's left is on a cycle or downstream of one. Peeling off the
+            // wires that feed nothing else left over leaves just the cycles.
+            let mut cycle: HashSet<&String> = n_inputs
+                .into_iter()
+                .filter(|(_, n)| *n > 0)
+                .map(|(wire, _)| wire)
+                .collect();
+
+            while let Some(&wire) = cycle.iter().find(|&&wire| {
+                !dependents
+                    .get(wire)
+                    .is_some_and(|d| d.iter().any(|d| cycle.contains(d)))
+            }) {
+                cycle.remove(wire);
+            }
 
-    let val = match instruction {
-        Provide(Literal(val)) => *val,
-        Provide(Wire(w)) => resolve(w, connections, resolved_map),
-        And(Literal(val), b) => {
-            let b = resolve(b, connections, resolved_map);
+            let mut cycle: Vec<&String> = cycle.into_iter().collect();
+            cycle.sort();
 
-            val & b
+            return Err(format!("Wires {:?} are part of a cycle", cycle));
         }
-        And(Wire(a), b) => {
-            let a = resolve(a, connections, resolved_map);
-            let b = resolve(b, connections, resolved_map);
 
-            a & b
+        let mut circuit = Circuit {
+            connections,
+            order,
+            dependents,
+            values: HashMap::new(),
+        };
+
+        let order = circuit.order.clone();
+        circuit.evaluate(&order);
+
+        Ok(circuit)
+    }
+
+    fn evaluate(&mut self, wires: &[String]) {
+        // `wires` must be in topological order.
+        for wire in wires {
+            let get = |w: &String| self.values[w];
+
+            let val = match &self.connections[wire] {
+                Provide(Literal(val)) => *val as u16,
+                Provide(Wire(a)) => get(a),
+                And(Literal(val), b) => *val as u16 & get(b),
+                And(Wire(a), b) => get(a) & get(b),
+                OR(a, b) => get(a) | get(b),
+                Not(a) => !get(a),
+                Lshift(a, n) => get(a) << n,
+                Rshift(a, n) => get(a) >> n,
+            };
+
+            self.values.insert(wire.clone(), val);
         }
-        OR(a, b) => {
-            let a = resolve(a, connections, resolved_map);
-            let b = resolve(b, connections, resolved_map);
+    }
 
-            a | b
+    fn get(&self, wire: &str) -> u16 {
+        self.values[wire]
+    }
+
+    fn set(&mut self, wire: &str, val: u16) {
+        // Override `wire` with a constant signal and re-evaluate only the wires
+        // downstream of it.
+        let old = self
+            .connections
+            .insert(wire.to_string(), Provide(Literal(val as usize)))
+            .unwrap_or_else(|| panic!("Wire {wire} is not part of the circuit"));
+
+        for input in old.inputs() {
+            self.dependents.get_mut(input).unwrap().remove(wire);
         }
-        Not(a) => !resolve(a, connections, resolved_map),
-        Lshift(a, n) => resolve(a, connections, resolved_map) << n,
-        Rshift(a, n) => resolve(a, connections, resolved_map) >> n,
-    };
 
-    resolved_map.insert(wire, val);
+        let mut cone = HashSet::from([wire.to_string()]);
+        let mut to_visit = vec![wire.to_string()];
+
+        while let Some(w) = to_visit.pop() {
+            for dependent in self.dependents.get(&w).into_iter().flatten() {
+                if cone.insert(dependent.clone()) {
+                    to_visit.push(dependent.clone());
+                }
+            }
+        }
+
+        let wires: Vec<String> = self
+            .order
+            .iter()
+            .filter(|w| cone.contains(*w))
+            .cloned()
+            .collect();
+
+        self.evaluate(&wires);
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+
+        for wire in &self.order {
+            let instruction = &self.connections[wire];
+
+            dot.push_str(&format!(
+                "    {wire} [label=\"{wire}\\n{}\"];\n",
+                instruction.label()
+            ));
+
+            for input in instruction.inputs() {
+                dot.push_str(&format!("    {input} -> {wire};\n"));
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
 
-    val
+fn build(input: &str) -> Circuit {
+    Circuit::new(parse(input)).unwrap_or_else(|reason| panic!("Invalid circuit: {reason}"))
 }
 
 fn p1(input: &str) -> usize {
-    let connections = parse(input);
-    resolve("a", &connections, &mut HashMap::new())
+    build(input).get("a") as usize
 }
 
 fn p2(input: &str) -> usize {
-    let mut connections = parse(input);
-    let val = resolve("a", &connections, &mut HashMap::new());
-    *connections.get_mut("b").unwrap() = Provide(Literal(val));
-    resolve("a", &connections, &mut HashMap::new())
+    let mut circuit = build(input);
+    circuit.set("b", circuit.get("a"));
+    circuit.get("a") as usize
 }
 
 fn main() {
@@ -125,6 +283,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "dot" => print!("{}", build(&input).to_dot()),
         _ => panic!("Invalid part"),
     };
 }
@@ -144,4 +303,64 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 72);
     }
+
+    #[test]
+    fn test_evaluate() {
+        let circuit = build(EXAMPLE);
+
+        let expected = [
+            ("a", 72),
+            ("e", 507),
+            ("f", 492),
+            ("g", 114),
+            ("h", 65412),
+            ("i", 65079),
+            ("x", 123),
+            ("b", 456),
+        ];
+
+        for (wire, val) in expected {
+            assert_eq!(circuit.get(wire), val);
+        }
+    }
+
+    #[test]
+    fn test_set() {
+        let mut circuit = build(EXAMPLE);
+        circuit.set("x", 1);
+
+        assert_eq!(circuit.get("h"), 65534);
+        assert_eq!(circuit.get("a"), 0);
+        // Not downstream of x, so untouched.
+        assert_eq!(circuit.get("i"), 65079);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let input = "1 -> x\nx AND z -> y\ny -> z\nz -> a";
+
+        assert_eq!(
+            Circuit::new(parse(input)).err(),
+            Some("Wires [\"y\", \"z\"] are part of a cycle".to_string())
+        );
+    }
+
+    #[test]
+    fn test_repeated_input() {
+        let input = "3 -> x\nx OR x -> a\nx AND x -> b\n5 -> c";
+        let circuit = Circuit::new(parse(input)).unwrap();
+
+        assert_eq!(circuit.get("a"), 3);
+        assert_eq!(circuit.get("b"), 3);
+    }
+
+    #[test]
+    fn test_undefined_wire() {
+        let input = "1 -> x\nx OR w -> a";
+
+        assert_eq!(
+            Circuit::new(parse(input)).err(),
+            Some("Wire w feeds a but is never defined".to_string())
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, env, fs};
+use std::{collections::BTreeMap, env, fs};
 
 use Command::*;
 use Content::*;
@@ -29,7 +29,7 @@ impl Command {
 #[derive(Debug)]
 enum Content {
     Directory(String),
-    File(usize),
+    File(String, usize),
 }
 
 impl Content {
@@ -40,7 +40,7 @@ impl Content {
             Directory(second.trim().to_owned())
         } else {
             let size: usize = first.parse().unwrap();
-            File(size)
+            File(second.trim().to_owned(), size)
         }
     }
 }
@@ -65,71 +65,230 @@ fn parse(input: &str) -> Vec<TerminalLine> {
     input.lines().map(TerminalLine::from).collect()
 }
 
-fn get_directory_sizes(lines: &[TerminalLine]) -> HashMap<String, usize> {
-    // directory paths are the keys and values are the sizes
-    let mut sizes: HashMap<String, usize> = HashMap::from([("/".to_owned(), 0)]);
-    let mut paths: Vec<String> = Vec::new();
-
-    for line in lines {
-        match line {
-            Input(ls) => continue,
-            Input(cd(dir)) => {
-                if dir == ".." {
-                    paths.pop();
-                } else {
-                    let next_cwd = if let Some(cwd) = paths.last() {
-                        format!("{cwd}{dir}")
-                    } else {
-                        dir.to_owned()
-                    };
-                    paths.push(next_cwd);
+#[derive(Debug)]
+enum Node {
+    Dir {
+        name: String,
+        parent: usize,
+        children: BTreeMap<String, usize>,
+    },
+    File {
+        name: String,
+        size: usize,
+    },
+}
+
+const ROOT: usize = 0;
+
+#[derive(Debug)]
+struct FileSystem {
+    // Nodes refer to each other by their index. The root is its own parent.
+    nodes: Vec<Node>,
+}
+
+impl FileSystem {
+    fn new() -> FileSystem {
+        FileSystem {
+            nodes: vec![Node::Dir {
+                name: "/".to_owned(),
+                parent: ROOT,
+                children: BTreeMap::new(),
+            }],
+        }
+    }
+
+    fn from(lines: &[TerminalLine]) -> Result<FileSystem, String> {
+        let mut fs = FileSystem::new();
+        let mut cwd = ROOT;
+
+        for line in lines {
+            match line {
+                Input(ls) => continue,
+                Input(cd(path)) => cwd = fs.resolve(cwd, path)?,
+                Output(Directory(name)) => {
+                    fs.mkdir(cwd, name)?;
                 }
+                Output(File(name, size)) => fs.touch(cwd, name, *size)?,
             }
-            Output(Directory(name)) => {
-                let parent = paths.last().unwrap();
-                let full_path = format!("{parent}{name}");
-                sizes.insert(full_path, 0);
+        }
+
+        Ok(fs)
+    }
+
+    fn children(&self, dir: usize) -> &BTreeMap<String, usize> {
+        match &self.nodes[dir] {
+            Node::Dir { children, .. } => children,
+            Node::File { name, .. } => panic!("{name} is not a directory"),
+        }
+    }
+
+    fn insert(&mut self, dir: usize, name: &str, node: Node) -> Result<usize, String> {
+        // Listing the same directory twice must not duplicate its contents, so
+        // an existing entry of the same name is reused, as long as it is of
+        // the same kind.
+        if let Some(&id) = self.children(dir).get(name) {
+            return match (&self.nodes[id], &node) {
+                (Node::Dir { .. }, Node::Dir { .. }) | (Node::File { .. }, Node::File { .. }) => {
+                    Ok(id)
+                }
+                (Node::File { .. }, Node::Dir { .. }) => Err(format!(
+                    "{} is a file, not a directory",
+                    self.child_path(dir, name)
+                )),
+                (Node::Dir { .. }, Node::File { .. }) => Err(format!(
+                    "{} is a directory, not a file",
+                    self.child_path(dir, name)
+                )),
+            };
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(node);
+
+        if let Node::Dir { children, .. } = &mut self.nodes[dir] {
+            children.insert(name.to_owned(), id);
+        }
+
+        Ok(id)
+    }
+
+    fn mkdir(&mut self, dir: usize, name: &str) -> Result<usize, String> {
+        let node = Node::Dir {
+            name: name.to_owned(),
+            parent: dir,
+            children: BTreeMap::new(),
+        };
+
+        self.insert(dir, name, node)
+    }
+
+    fn touch(&mut self, dir: usize, name: &str, size: usize) -> Result<(), String> {
+        let node = Node::File {
+            name: name.to_owned(),
+            size,
+        };
+
+        let id = self.insert(dir, name, node)?;
+
+        if let Node::File { size: s, .. } = &mut self.nodes[id] {
+            *s = size;
+        }
+
+        Ok(())
+    }
+
+    fn resolve(&mut self, cwd: usize, path: &str) -> Result<usize, String> {
+        // Follow an absolute or relative path, made of any number of
+        // components. Directories we haven't seen listed yet are created.
+        let start = if path.starts_with('/') { ROOT } else { cwd };
+
+        path.split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .try_fold(start, |dir, component| {
+                match (component, &self.nodes[dir]) {
+                    ("..", Node::Dir { parent, .. }) => Ok(*parent),
+                    _ => self.mkdir(dir, component),
+                }
+            })
+    }
+
+    fn child_path(&self, dir: usize, name: &str) -> String {
+        match dir {
+            ROOT => format!("/{name}"),
+            _ => format!("{}/{name}", self.path(dir)),
+        }
+    }
+
+    fn path(&self, id: usize) -> String {
+        match &self.nodes[id] {
+            _ if id == ROOT => "/".to_owned(),
+            Node::Dir { name, parent, .. } if *parent == ROOT => format!("/{name}"),
+            Node::Dir { name, parent, .. } => format!("{}/{name}", self.path(*parent)),
+            Node::File { name, .. } => name.to_owned(),
+        }
+    }
+
+    fn sizes(&self, id: usize, acc: &mut Vec<(usize, usize)>) -> usize {
+        // Size of `id`, while collecting (directory, size) pairs in post-order.
+        match &self.nodes[id] {
+            Node::File { size, .. } => *size,
+            Node::Dir { children, .. } => {
+                let size = children.values().map(|&c| self.sizes(c, acc)).sum();
+                acc.push((id, size));
+                size
+            }
+        }
+    }
+
+    fn directory_sizes(&self) -> Vec<(usize, usize)> {
+        let mut acc = Vec::new();
+        self.sizes(ROOT, &mut acc);
+        acc
+    }
+
+    fn du(&self) -> String {
+        self.directory_sizes()
+            .into_iter()
+            .map(|(id, size)| format!("{size}\t{}\n", self.path(id)))
+            .collect()
+    }
+
+    fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(ROOT, 0, &mut out);
+        out
+    }
+
+    fn write_tree(&self, id: usize, depth: usize, out: &mut String) {
+        let indent = "  ".repeat(depth);
+
+        match &self.nodes[id] {
+            Node::File { name, size } => {
+                out.push_str(&format!("{indent}- {name} (file, size={size})\n"))
             }
-            Output(File(size)) => {
-                for p in paths.iter() {
-                    *sizes.get_mut(p).unwrap() += size;
+            Node::Dir { name, children, .. } => {
+                out.push_str(&format!("{indent}- {name} (dir)\n"));
+
+                for &child in children.values() {
+                    self.write_tree(child, depth + 1, out);
                 }
             }
         }
     }
+}
 
-    sizes
+fn build(input: &str) -> FileSystem {
+    FileSystem::from(&parse(input)).unwrap_or_else(|reason| panic!("Invalid session: {reason}"))
 }
 
 fn p1(input: &str) -> usize {
-    let lines = parse(input);
+    let fs = build(input);
 
-    get_directory_sizes(&lines)
-        .into_values()
-        .filter(|&v| v <= 100000)
+    fs.directory_sizes()
+        .into_iter()
+        .map(|(_, size)| size)
+        .filter(|&size| size <= 100000)
         .sum()
 }
 
 fn p2(input: &str) -> usize {
-    let lines = parse(input);
+    let fs = build(input);
 
-    let directory_sizes = get_directory_sizes(&lines);
+    let directory_sizes = fs.directory_sizes();
 
     let disk_space_total: usize = 70000000;
     let disk_space_required: usize = 30000000;
 
-    let disk_space_unused = disk_space_total - directory_sizes.get("/").unwrap();
+    // The root is the last directory in post-order.
+    let (_, used) = directory_sizes.last().unwrap();
+
+    let disk_space_unused = disk_space_total - used;
     let disk_space_needed = disk_space_required - disk_space_unused;
 
-    *directory_sizes
+    directory_sizes
         .iter()
-        .filter_map(|(p, s)| {
-            if p != "/" && s >= &disk_space_needed {
-                Some(s)
-            } else {
-                None
-            }
-        })
+        .filter(|(id, size)| *id != ROOT && *size >= disk_space_needed)
+        .map(|(_, size)| *size)
         .min()
         .unwrap()
 }
@@ -145,6 +304,8 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "du" => print!("{}", build(&input).du()),
+        "tree" => print!("{}", build(&input).tree()),
         _ => panic!("Invalid part"),
     };
 }
@@ -164,4 +325,60 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 24933642);
     }
+
+    #[test]
+    fn test_revisits() {
+        // Going back to directories, and listing them again, changes nothing.
+        let revisits = format!(
+            "{EXAMPLE}\n$ cd /\n$ ls\ndir a\n14848514 b.txt\n$ cd a/e\n$ ls\n584 i\n$ cd /d\n$ ls\n7214296 k\n$ cd ../a\n$ ls\n29116 f"
+        );
+
+        assert_eq!(p1(&revisits), 95437);
+        assert_eq!(p2(&revisits), 24933642);
+        assert_eq!(build(&revisits).tree(), build(EXAMPLE).tree());
+    }
+
+    #[test]
+    fn test_tree() {
+        let fs = build(EXAMPLE);
+
+        assert_eq!(
+            fs.tree(),
+            "- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+"
+        );
+
+        assert_eq!(fs.du(), "584\t/a/e\n94853\t/a\n24933642\t/d\n48381165\t/\n");
+    }
+
+    #[test]
+    fn test_clash() {
+        let clash = |input: &str| FileSystem::from(&parse(input)).err();
+
+        assert_eq!(
+            clash("$ cd /\n$ ls\ndir a\n$ cd a\n$ ls\n100 b\n$ cd b"),
+            Some("/a/b is a file, not a directory".to_string())
+        );
+        assert_eq!(
+            clash("$ cd /\n$ ls\n100 a\n$ ls\ndir a"),
+            Some("/a is a file, not a directory".to_string())
+        );
+        assert_eq!(
+            clash("$ cd /\n$ cd a\n$ cd /\n$ ls\n100 a"),
+            Some("/a is a directory, not a file".to_string())
+        );
+    }
 }
@@ -1,45 +1,181 @@
+use num_bigint::BigInt;
 use std::{
     collections::{HashMap, VecDeque},
-    env, fs,
+    env,
+    fmt::Debug,
+    fs,
 };
 
-#[derive(Debug)]
-enum Operator {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
     Add,
+    Sub,
     Mul,
+    Div,
 }
 
-#[derive(Debug)]
-enum Operand {
-    Num(usize),
+#[derive(Debug, PartialEq)]
+enum Expr {
     Old,
+    Num(u64),
+    Binary(Box<Expr>, Op, Box<Expr>),
 }
 
-#[derive(Debug)]
-struct Operation {
-    operator: Operator,
-    operand: Operand,
+impl Expr {
+    fn from(expression: &str) -> Expr {
+        // Operands and operators are separated by spaces. `*` and `/` bind
+        // tighter than `+` and `-`, and everything is left associative.
+        let tokens: Vec<&str> = expression.split_whitespace().collect();
+
+        let operand = |token: &str| match token {
+            "old" => Expr::Old,
+            n => Expr::Num(n.parse().unwrap_or_else(|_| panic!("Invalid operand {n}"))),
+        };
+
+        let operator = |token: &str| match token {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            t => panic!("Invalid operator {t}"),
+        };
+
+        let mut sum: Option<(Expr, Op)> = None;
+        let mut product = operand(tokens[0]);
+
+        for pair in tokens[1..].chunks(2) {
+            let op = operator(pair[0]);
+            let rhs = operand(pair[1]);
+
+            match op {
+                Op::Mul | Op::Div => product = Expr::Binary(Box::new(product), op, Box::new(rhs)),
+                Op::Add | Op::Sub => {
+                    let lhs = match sum.take() {
+                        Some((lhs, sum_op)) => {
+                            Expr::Binary(Box::new(lhs), sum_op, Box::new(product))
+                        }
+                        None => product,
+                    };
+
+                    sum = Some((lhs, op));
+                    product = rhs;
+                }
+            }
+        }
+
+        match sum {
+            Some((lhs, op)) => Expr::Binary(Box::new(lhs), op, Box::new(product)),
+            None => product,
+        }
+    }
+
+    fn eval<W: Worry>(&self, old: &W) -> W {
+        match self {
+            Expr::Old => old.clone(),
+            Expr::Num(n) => old.lift(*n),
+            Expr::Binary(lhs, op, rhs) => lhs.eval(old).apply(*op, &rhs.eval(old)),
+        }
+    }
+}
+
+// How worry levels are represented. `Modular` keeps them bounded by working
+// modulo the product of all the divisibility tests, which doesn't change the
+// outcome of any test, but can't divide. `Exact` never loses information, but
+// grows without bounds.
+trait Worry: Clone + Debug {
+    fn lift(&self, n: u64) -> Self;
+    fn apply(&self, op: Op, rhs: &Self) -> Self;
+    fn relieve(&self, divide_by: u64) -> Self;
+    fn is_divisible_by(&self, n: u64) -> bool;
+}
+
+#[derive(Debug, Clone)]
+struct Modular {
+    value: u64,
+    modulus: u64,
+}
+
+impl Worry for Modular {
+    fn lift(&self, n: u64) -> Self {
+        Modular {
+            value: n % self.modulus,
+            modulus: self.modulus,
+        }
+    }
+
+    fn apply(&self, op: Op, rhs: &Self) -> Self {
+        let (a, b, m) = (self.value as u128, rhs.value as u128, self.modulus as u128);
+
+        let value = match op {
+            Op::Add => (a + b) % m,
+            Op::Sub => (a + m - b) % m,
+            Op::Mul => (a * b) % m,
+            Op::Div => panic!("Division isn't possible in the modular ring"),
+        };
+
+        Modular {
+            value: value as u64,
+            modulus: self.modulus,
+        }
+    }
+
+    fn relieve(&self, divide_by: u64) -> Self {
+        assert_eq!(divide_by, 1, "Relief isn't possible in the modular ring");
+        self.clone()
+    }
+
+    fn is_divisible_by(&self, n: u64) -> bool {
+        self.value.is_multiple_of(n)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Exact(BigInt);
+
+impl Worry for Exact {
+    fn lift(&self, n: u64) -> Self {
+        Exact(BigInt::from(n))
+    }
+
+    fn apply(&self, op: Op, rhs: &Self) -> Self {
+        let (a, b) = (&self.0, &rhs.0);
+
+        Exact(match op {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div => a / b,
+        })
+    }
+
+    fn relieve(&self, divide_by: u64) -> Self {
+        Exact(&self.0 / divide_by)
+    }
+
+    fn is_divisible_by(&self, n: u64) -> bool {
+        (&self.0 % n) == BigInt::ZERO
+    }
 }
 
 #[derive(Debug)]
 struct Test {
-    divisible_by: usize,
+    divisible_by: u64,
     on_true: usize,
     on_false: usize,
 }
 
 #[derive(Debug)]
-struct Monkey {
-    items: VecDeque<usize>,
-    operation: Operation,
+struct Monkey<W> {
+    items: VecDeque<W>,
+    operation: Expr,
     test: Test,
 
     n_inpections: usize,
 }
 
-type Monkeys = HashMap<usize, Monkey>;
+type Monkeys<W> = HashMap<usize, Monkey<W>>;
 
-fn parse(input: &str) -> Monkeys {
+fn parse(input: &str) -> Monkeys<u64> {
     input
         .split("\n\n")
         .map(|block| {
@@ -69,19 +205,7 @@ fn parse(input: &str) -> Monkeys {
                 .collect();
 
             // 3. operation
-            let operation = operation.strip_prefix("Operation: new = old ").unwrap();
-            let (operator, operand) = operation.split_once(' ').unwrap();
-            let operator = if operator == "*" {
-                Operator::Mul
-            } else {
-                Operator::Add
-            };
-            let operand = if operand == "old" {
-                Operand::Old
-            } else {
-                Operand::Num(operand.parse().unwrap())
-            };
-            let operation = Operation { operator, operand };
+            let operation = Expr::from(operation.strip_prefix("Operation: new = ").unwrap());
 
             // 4. test
             let divisible_by = test
@@ -123,35 +247,26 @@ fn parse(input: &str) -> Monkeys {
         .collect()
 }
 
-impl Monkey {
-    fn throw(&mut self, divide_by: usize, modulo: usize) -> Option<(usize, usize)> {
+impl<W: Worry> Monkey<W> {
+    fn throw(&mut self, divide_by: u64) -> Option<(W, usize)> {
         let Monkey {
             operation,
             test,
             items,
             n_inpections,
         } = self;
-        let Operation { operator, operand } = operation;
         let Test {
             divisible_by,
             on_true,
             on_false,
         } = test;
 
-        if let Some(mut worry_level) = items.pop_front() {
+        if let Some(worry_level) = items.pop_front() {
             *n_inpections += 1;
 
-            worry_level = match (operator, operand) {
-                (Operator::Add, Operand::Num(n)) => worry_level + *n,
-                (Operator::Mul, Operand::Num(n)) => worry_level * *n,
-                (Operator::Add, Operand::Old) => worry_level + worry_level,
-                (Operator::Mul, Operand::Old) => worry_level * worry_level,
-            };
-            worry_level /= divide_by;
-
-            worry_level %= modulo;
+            let worry_level = operation.eval(&worry_level).relieve(divide_by);
 
-            if worry_level.is_multiple_of(*divisible_by) {
+            if worry_level.is_divisible_by(*divisible_by) {
                 Some((worry_level, *on_true))
             } else {
                 Some((worry_level, *on_false))
@@ -161,14 +276,14 @@ impl Monkey {
         }
     }
 
-    fn catch(&mut self, worry_level: usize) {
+    fn catch(&mut self, worry_level: W) {
         self.items.push_back(worry_level);
     }
 
-    fn throw_all(&mut self, divide_by: usize, modulo: usize) -> Vec<(usize, usize)> {
+    fn throw_all(&mut self, divide_by: u64) -> Vec<(W, usize)> {
         let mut to_throw = Vec::new();
 
-        while let Some(thing) = self.throw(divide_by, modulo) {
+        while let Some(thing) = self.throw(divide_by) {
             to_throw.push(thing);
         }
 
@@ -176,11 +291,38 @@ impl Monkey {
     }
 }
 
-fn simulate_round(mut monkeys: Monkeys, divide_by: usize, modulo: usize) -> Monkeys {
+impl Monkey<u64> {
+    fn with_worry<W>(self, lift: impl Fn(u64) -> W) -> Monkey<W> {
+        Monkey {
+            items: self.items.into_iter().map(lift).collect(),
+            operation: self.operation,
+            test: self.test,
+            n_inpections: self.n_inpections,
+        }
+    }
+}
+
+fn modular(monkeys: Monkeys<u64>) -> Monkeys<Modular> {
+    let modulus = monkeys.values().map(|m| m.test.divisible_by).product();
+
+    monkeys
+        .into_iter()
+        .map(|(id, m)| (id, m.with_worry(|value| Modular { value, modulus })))
+        .collect()
+}
+
+fn exact(monkeys: Monkeys<u64>) -> Monkeys<Exact> {
+    monkeys
+        .into_iter()
+        .map(|(id, m)| (id, m.with_worry(|value| Exact(BigInt::from(value)))))
+        .collect()
+}
+
+fn simulate_round<W: Worry>(mut monkeys: Monkeys<W>, divide_by: u64) -> Monkeys<W> {
     let len = monkeys.len();
 
     for id in 0..len {
-        let things = monkeys.get_mut(&id).unwrap().throw_all(divide_by, modulo);
+        let things = monkeys.get_mut(&id).unwrap().throw_all(divide_by);
 
         for (worry_level, to) in things {
             monkeys.get_mut(&to).unwrap().catch(worry_level);
@@ -190,18 +332,22 @@ fn simulate_round(mut monkeys: Monkeys, divide_by: usize, modulo: usize) -> Monk
     monkeys
 }
 
-fn monkey_business(rounds: usize, mut monkeys: Monkeys, divide_by: usize) -> usize {
-    let modulo = monkeys.values().map(|m| m.test.divisible_by).product();
+fn trace<W: Worry>(rounds: usize, mut monkeys: Monkeys<W>, divide_by: u64) -> Vec<Vec<usize>> {
+    // The number of inspections of every monkey, after every round.
+    (0..rounds)
+        .map(|_| {
+            monkeys = simulate_round(std::mem::take(&mut monkeys), divide_by);
 
-    for _ in 0..rounds {
-        monkeys = simulate_round(monkeys, divide_by, modulo);
-    }
+            (0..monkeys.len())
+                .map(|id| monkeys[&id].n_inpections)
+                .collect()
+        })
+        .collect()
+}
 
-    let inspections_count = {
-        let mut inspections_count: Vec<usize> = monkeys.values().map(|m| m.n_inpections).collect();
-        inspections_count.sort_unstable();
-        inspections_count
-    };
+fn monkey_business<W: Worry>(rounds: usize, monkeys: Monkeys<W>, divide_by: u64) -> usize {
+    let mut inspections_count = trace(rounds, monkeys, divide_by).pop().unwrap();
+    inspections_count.sort_unstable();
 
     inspections_count
         .iter()
@@ -210,11 +356,12 @@ fn monkey_business(rounds: usize, mut monkeys: Monkeys, divide_by: usize) -> usi
 }
 
 fn p1(input: &str) -> usize {
-    monkey_business(20, parse(input), 3)
+    // Relief divides the worry levels, so they have to be tracked exactly.
+    monkey_business(20, exact(parse(input)), 3)
 }
 
 fn p2(input: &str) -> usize {
-    monkey_business(10000, parse(input), 1)
+    monkey_business(10000, modular(parse(input)), 1)
 }
 
 fn main() {
@@ -247,4 +394,41 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 2713310158);
     }
+
+    #[test]
+    fn test_expr() {
+        use Expr::*;
+
+        let expr = Expr::from("old * old + 3 - old / 2");
+
+        assert_eq!(
+            expr,
+            Binary(
+                Box::new(Binary(
+                    Box::new(Binary(Box::new(Old), Op::Mul, Box::new(Old))),
+                    Op::Add,
+                    Box::new(Num(3)),
+                )),
+                Op::Sub,
+                Box::new(Binary(Box::new(Old), Op::Div, Box::new(Num(2)))),
+            )
+        );
+
+        assert_eq!(expr.eval(&Exact(BigInt::from(10))).0, BigInt::from(98));
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        // Without relief, worry levels grow quickly (some monkeys square
+        // them), but they are still small enough to be tracked exactly for
+        // the first few rounds.
+        let rounds = 10;
+
+        assert_eq!(
+            trace(rounds, modular(parse(EXAMPLE)), 1),
+            trace(rounds, exact(parse(EXAMPLE)), 1)
+        );
+
+        assert_eq!(trace(1, exact(parse(EXAMPLE)), 1)[0], vec![2, 4, 3, 6]);
+    }
 }
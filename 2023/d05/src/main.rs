@@ -44,74 +44,196 @@ fn parse(input: &str) -> (Vec<usize>, Maps) {
     (seeds, maps)
 }
 
-fn map_range(map: &Map, (input_start, input_range): Range) -> Ranges {
-    let input_end = input_start + input_range;
-
-    for (destination_start, source_start, range) in map {
-        let source_end = source_start + range;
-
-        // Cases to handle
-        // 1) Input range entire outside ALL source ranges.
-        // 2) Input range entirely inside some source range.
-        // 3) Input range partially inside some source range.
-        //      3.1) Input starts inside some source range, ends outside some source range.
-        //      3.2) Input starts outside some source range, ends inside some source range.
-        //      3.3) Input starts outside some source range, ends outside some source range.
-
-        if input_end <= *source_start || input_start >= source_end {
-            continue;
-        } else if input_start >= *source_start && input_end <= source_end {
-            return vec![(destination_start + input_start - source_start, input_range)];
-        } else if input_start >= *source_start && input_end > source_end {
-            return map_range(map, (input_start, source_end - input_start))
-                .into_iter()
-                .chain(map_range(map, (source_end, input_end - source_end)))
-                .collect();
-        } else if input_start < *source_start && input_end <= source_end {
-            return map_range(map, (input_start, source_start - input_start))
-                .into_iter()
-                .chain(map_range(map, (*source_start, input_end - source_start)))
-                .collect();
-        } else if input_start < *source_start && input_end > source_end {
-            return map_range(map, (input_start, source_start - input_start))
-                .into_iter()
-                .chain(map_range(map, (*source_start, *range)))
-                .chain(map_range(map, (source_end, input_end - source_end)))
-                .collect();
+// A function on the naturals made of translated pieces. Piece `k` covers
+// `starts[k]..starts[k + 1]` (the last one extends to infinity) and adds
+// `offsets[k]` to everything in it. Every `Map` is such a function, and so is
+// any composition of them.
+#[derive(Debug, Clone, PartialEq)]
+struct PiecewiseLinear {
+    starts: Vec<i64>,
+    offsets: Vec<i64>,
+}
+
+impl PiecewiseLinear {
+    fn identity() -> PiecewiseLinear {
+        PiecewiseLinear {
+            starts: vec![0],
+            offsets: vec![0],
         }
     }
 
-    vec![(input_start, input_range)]
+    fn from(map: &Map) -> PiecewiseLinear {
+        let mut ranges = map.clone();
+        ranges.sort_by_key(|&(_, source_start, _)| source_start);
+
+        let mut pieces = vec![];
+        let mut position = 0;
+
+        for (destination_start, source_start, range) in ranges {
+            let (destination_start, source_start) = (destination_start as i64, source_start as i64);
+
+            if source_start > position {
+                pieces.push((position, 0));
+            }
+
+            pieces.push((source_start, destination_start - source_start));
+            position = source_start + range as i64;
+        }
+
+        pieces.push((position, 0));
+
+        PiecewiseLinear::from_pieces(pieces)
+    }
+
+    fn from_pieces(pieces: Vec<(i64, i64)>) -> PiecewiseLinear {
+        // Drops empty pieces and merges neighbours with the same offset, so
+        // equal functions end up with equal breakpoints.
+        let mut f = PiecewiseLinear {
+            starts: vec![],
+            offsets: vec![],
+        };
+
+        for (start, offset) in pieces {
+            if f.starts.last() == Some(&start) {
+                f.starts.pop();
+                f.offsets.pop();
+            }
+
+            if f.offsets.last() != Some(&offset) {
+                f.starts.push(start);
+                f.offsets.push(offset);
+            }
+        }
+
+        f
+    }
+
+    fn pieces(&self) -> impl Iterator<Item = (i64, Option<i64>, i64)> + '_ {
+        // (start, exclusive end, offset) of every piece.
+        (0..self.starts.len()).map(|k| {
+            (
+                self.starts[k],
+                self.starts.get(k + 1).copied(),
+                self.offsets[k],
+            )
+        })
+    }
+
+    fn apply(&self, x: usize) -> usize {
+        let k = self.starts.partition_point(|&start| start <= x as i64) - 1;
+        (x as i64 + self.offsets[k]) as usize
+    }
+
+    fn apply_range(&self, (start, range): Range) -> Ranges {
+        let (start, end) = (start as i64, (start + range) as i64);
+
+        self.pieces()
+            .filter_map(|(s, e, offset)| {
+                let from = start.max(s);
+                let to = e.map_or(end, |e| end.min(e));
+
+                (from < to).then_some(((from + offset) as usize, (to - from) as usize))
+            })
+            .collect()
+    }
+
+    fn then(&self, g: &PiecewiseLinear) -> PiecewiseLinear {
+        // The composition x -> g(self(x)). Every piece of `self` maps to a
+        // contiguous interval, which is split wherever `g` has a breakpoint.
+        let mut pieces = vec![];
+
+        for (s, e, offset) in self.pieces() {
+            let (image_start, image_end) = (s + offset, e.map(|e| e + offset));
+
+            for (gs, ge, g_offset) in g.pieces() {
+                let from = image_start.max(gs);
+                let to = match (image_end, ge) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+
+                if to.is_none_or(|to| from < to) {
+                    pieces.push((from - offset, offset + g_offset));
+                }
+            }
+        }
+
+        PiecewiseLinear::from_pieces(pieces)
+    }
+
+    fn invert(&self) -> Option<PiecewiseLinear> {
+        // Only possible if the images of the pieces tile the naturals exactly,
+        // i.e., if the function is a bijection.
+        let mut images: Vec<(i64, Option<i64>, i64)> = self
+            .pieces()
+            .map(|(s, e, offset)| (s + offset, e.map(|e| e + offset), offset))
+            .collect();
+        images.sort();
+
+        let mut position = Some(0);
+
+        for &(start, end, _) in &images {
+            if position != Some(start) {
+                return None;
+            }
+
+            position = end;
+        }
+
+        Some(PiecewiseLinear::from_pieces(
+            images
+                .into_iter()
+                .map(|(start, _, offset)| (start, -offset))
+                .collect(),
+        ))
+    }
+}
+
+fn compose(maps: &Maps, from: &str, to: &str) -> PiecewiseLinear {
+    // Follow the chain of maps, e.g., "seed-to-soil", "soil-to-fertilizer",
+    // and so on, from category `from` to category `to`.
+    let mut f = PiecewiseLinear::identity();
+    let mut category = from.to_string();
+
+    while category != to {
+        let (name, map) = maps
+            .iter()
+            .find(|(name, _)| name.starts_with(&format!("{category}-to-")))
+            .unwrap_or_else(|| panic!("No map from {category}"));
+
+        f = f.then(&PiecewiseLinear::from(map));
+        category = name.rsplit("-to-").next().unwrap().to_string();
+    }
+
+    f
+}
+
+fn seed_for(input: &str, location: usize) -> Option<usize> {
+    // The maps can only be followed backwards if no two seeds share a
+    // location, i.e., if they compose into a bijection.
+    let (_, maps) = parse(input);
+
+    Some(compose(&maps, "seed", "location").invert()?.apply(location))
 }
 
 fn p1(input: &str) -> usize {
     let (seeds, maps) = parse(input);
+    let seed_to_location = compose(&maps, "seed", "location");
 
     seeds
         .iter()
-        .map(|&n| map_range(&maps["seed-to-soil"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["soil-to-fertilizer"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["fertilizer-to-water"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["water-to-light"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["light-to-temperature"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["temperature-to-humidity"], (n, 1))[0].0)
-        .map(|n| map_range(&maps["humidity-to-location"], (n, 1))[0].0)
+        .map(|&n| seed_to_location.apply(n))
         .min()
         .unwrap()
 }
 
 fn p2(input: &str) -> usize {
     let (seeds, maps) = parse(input);
+    let seed_to_location = compose(&maps, "seed", "location");
 
     seeds
         .chunks(2)
-        .flat_map(|w| map_range(&maps["seed-to-soil"], (w[0], w[1])))
-        .flat_map(|(n, r)| map_range(&maps["soil-to-fertilizer"], (n, r)))
-        .flat_map(|(n, r)| map_range(&maps["fertilizer-to-water"], (n, r)))
-        .flat_map(|(n, r)| map_range(&maps["water-to-light"], (n, r)))
-        .flat_map(|(n, r)| map_range(&maps["light-to-temperature"], (n, r)))
-        .flat_map(|(n, r)| map_range(&maps["temperature-to-humidity"], (n, r)))
-        .flat_map(|(n, r)| map_range(&maps["humidity-to-location"], (n, r)))
+        .flat_map(|w| seed_to_location.apply_range((w[0], w[1])))
         .map(|(n, _)| n)
         .min()
         .unwrap()
@@ -128,6 +250,14 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "seed" => {
+            let location = args[3].parse().unwrap();
+
+            match seed_for(&input, location) {
+                Some(seed) => println!("{seed}"),
+                None => println!("Several seeds share locations, so there's no way back"),
+            }
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -135,6 +265,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -147,4 +278,102 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 46);
     }
+
+    #[test]
+    fn test_invert() {
+        let (_, maps) = parse(EXAMPLE);
+        let seed_to_location = compose(&maps, "seed", "location");
+        let location_to_seed = seed_to_location.invert().unwrap();
+
+        // From the puzzle: seed 82 ends up at location 46.
+        assert_eq!(seed_to_location.apply(82), 46);
+        assert_eq!(location_to_seed.apply(46), 82);
+        assert_eq!(
+            seed_to_location.then(&location_to_seed),
+            PiecewiseLinear::identity()
+        );
+
+        // Both 0 and 1 map to 5, and nothing maps to 0.
+        let not_bijective = PiecewiseLinear::from(&vec![(5, 0, 1), (5, 1, 1)]);
+        assert_eq!(not_bijective.invert(), None);
+
+        // Seed 13 ends up at location 35, the lowest one.
+        assert_eq!(seed_for(EXAMPLE, 35), Some(13));
+    }
+
+    fn step(map: &Map, n: usize) -> usize {
+        map.iter()
+            .find(|&&(_, source_start, range)| (source_start..source_start + range).contains(&n))
+            .map_or(n, |&(destination_start, source_start, _)| {
+                destination_start + n - source_start
+            })
+    }
+
+    fn arbitrary_map() -> impl Strategy<Value = Map> {
+        // Non overlapping source ranges, sent to a shuffled non overlapping
+        // set of destination ranges.
+        prop::collection::vec(1..50usize, 1..6)
+            .prop_flat_map(|lengths| {
+                let n = lengths.len();
+                (
+                    Just(lengths),
+                    prop::collection::vec(0..20usize, n),
+                    Just((0..n).collect::<Vec<_>>()).prop_shuffle(),
+                )
+            })
+            .prop_map(|(lengths, gaps, order)| {
+                let sources = lengths
+                    .iter()
+                    .zip(&gaps)
+                    .scan(0, |position, (length, gap)| {
+                        *position += gap + length;
+                        Some(*position - length)
+                    });
+
+                let mut destination = 0;
+                let mut destinations = vec![0; lengths.len()];
+                for &k in &order {
+                    destinations[k] = destination;
+                    destination += lengths[k];
+                }
+
+                sources
+                    .zip(lengths.iter())
+                    .enumerate()
+                    .map(|(k, (source, &length))| (destinations[k], source, length))
+                    .collect()
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn test_compose(
+            chain in prop::collection::vec(arbitrary_map(), 1..5),
+            xs in prop::collection::vec(0..400usize, 20),
+            (start, range) in (0..400usize, 1..100usize),
+        ) {
+            let composed = chain
+                .iter()
+                .fold(PiecewiseLinear::identity(), |f, map| f.then(&PiecewiseLinear::from(map)));
+
+            for x in xs {
+                let expected = chain.iter().fold(x, |n, map| step(map, n));
+                prop_assert_eq!(composed.apply(x), expected);
+            }
+
+            let mut expected: Vec<usize> = (start..start + range)
+                .map(|x| chain.iter().fold(x, |n, map| step(map, n)))
+                .collect();
+            expected.sort();
+
+            let mut mapped: Vec<usize> = composed
+                .apply_range((start, range))
+                .into_iter()
+                .flat_map(|(n, r)| n..n + r)
+                .collect();
+            mapped.sort();
+
+            prop_assert_eq!(mapped, expected);
+        }
+    }
 }
@@ -1,91 +1,136 @@
-use std::{cmp::Ordering, collections::HashMap, env, fs};
+use std::{collections::HashMap, env, fs};
 
 struct CamelCard {
     hand: String,
     bid: usize,
 }
 
-fn card_to_score(c: &char, demote: bool) -> u8 {
-    match c {
-        '2'..='9' => c.to_digit(10).unwrap() as u8,
-        'T' => 10,
-        'J' => {
-            if demote {
-                1
+struct Category {
+    name: &'static str,
+    // Sizes of the groups of equal cards, largest first.
+    groups: Vec<usize>,
+}
+
+struct HandRules {
+    // Cards from weakest to strongest, used to break ties between hands of the
+    // same category.
+    order: Vec<char>,
+    // Cards that join whichever group makes the hand strongest.
+    wild: Vec<char>,
+    // From weakest to strongest.
+    categories: Vec<Category>,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Evaluation {
+    category: usize,
+    tiebreak: Vec<usize>,
+}
+
+impl HandRules {
+    fn categories() -> Vec<Category> {
+        [
+            ("High card", vec![1, 1, 1, 1, 1]),
+            ("One pair", vec![2, 1, 1, 1]),
+            ("Two pair", vec![2, 2, 1]),
+            ("Three of a kind", vec![3, 1, 1]),
+            ("Full house", vec![3, 2]),
+            ("Four of a kind", vec![4, 1]),
+            ("Five of a kind", vec![5]),
+        ]
+        .into_iter()
+        .map(|(name, groups)| Category { name, groups })
+        .collect()
+    }
+
+    fn standard() -> HandRules {
+        HandRules {
+            order: "23456789TJQKA".chars().collect(),
+            wild: vec![],
+            categories: HandRules::categories(),
+        }
+    }
+
+    fn jokers() -> HandRules {
+        // Jokers are wild, but the weakest card when breaking ties.
+        HandRules {
+            order: "J23456789TQKA".chars().collect(),
+            wild: vec!['J'],
+            categories: HandRules::categories(),
+        }
+    }
+
+    fn strength(&self, card: char) -> usize {
+        self.order
+            .iter()
+            .position(|&c| c == card)
+            .unwrap_or_else(|| panic!("Unknown card {card}"))
+    }
+
+    fn groups(&self, hand: &str) -> (Vec<(char, usize)>, usize) {
+        // Groups of equal (non wild) cards, largest and then strongest first,
+        // along with the number of wild cards.
+        let mut counter: HashMap<char, usize> = HashMap::new();
+        let mut n_wild = 0;
+
+        for c in hand.chars() {
+            if self.wild.contains(&c) {
+                n_wild += 1;
             } else {
-                11
+                *counter.entry(c).or_default() += 1;
             }
         }
-        'Q' => 12,
-        'K' => 13,
-        'A' => 14,
-        _ => panic!("Unknown card"),
+
+        let mut groups: Vec<(char, usize)> = counter.into_iter().collect();
+        groups.sort_by_key(|&(c, n)| std::cmp::Reverse((n, self.strength(c))));
+
+        (groups, n_wild)
     }
-}
 
-impl CamelCard {
-    fn hand_score(&self, promote: bool) -> usize {
-        let mut counter = HashMap::new();
-
-        self.hand
-            .chars()
-            .for_each(|c| *counter.entry(c).or_default() += 1);
-
-        if promote {
-            if let Some(j_count) = counter.remove(&'J') {
-                if counter.is_empty() {
-                    counter.insert('A', j_count);
-                } else {
-                    let max_count: &usize = counter.values().max().unwrap();
-                    let promotion_candidates: Vec<&char> = counter
-                        .iter()
-                        .filter_map(|(k, &v)| if v == *max_count { Some(k) } else { None })
-                        .collect();
-
-                    let final_candidate: &char = match promotion_candidates.len() {
-                        1 => promotion_candidates[0],
-                        _ => promotion_candidates
-                            .iter()
-                            .max_by_key(|&&c| card_to_score(c, false))
-                            .unwrap(),
-                    };
-
-                    counter
-                        .entry(*final_candidate)
-                        .and_modify(|v| *v += j_count);
-                }
-            }
+    fn group_sizes(&self, hand: &str) -> Vec<usize> {
+        // Wild cards are best spent making the largest group even larger.
+        let (groups, n_wild) = self.groups(hand);
+        let mut sizes: Vec<usize> = groups.iter().map(|&(_, n)| n).collect();
+
+        match sizes.first_mut() {
+            Some(largest) => *largest += n_wild,
+            None => sizes.push(n_wild),
         }
 
-        let counts: Vec<usize> = {
-            let mut counts: Vec<usize> = counter.values().cloned().collect();
-            counts.sort_unstable();
-            counts
-        };
+        sizes
+    }
+
+    fn evaluate(&self, hand: &str) -> Evaluation {
+        let sizes = self.group_sizes(hand);
+
+        let category = self
+            .categories
+            .iter()
+            .position(|category| category.groups == sizes)
+            .unwrap_or_else(|| panic!("{hand} doesn't fit any category"));
 
-        match counter.len() {
-            1 => 6,
-            2 => match counts.as_slice() {
-                [1, 4] => 5,
-                [2, 3] => 4,
-                _ => unreachable!(),
-            },
-            3 => match counts.as_slice() {
-                [1, 1, 3] => 3,
-                [1, 2, 2] => 2,
-                _ => unreachable!(),
-            },
-            4 => 1,
-            5 => 0,
-            _ => unreachable!(),
+        Evaluation {
+            category,
+            tiebreak: hand.chars().map(|c| self.strength(c)).collect(),
         }
     }
 
-    fn card_scores(&self, demote: bool) -> Vec<u8> {
-        self.hand
-            .chars()
-            .map(|c| card_to_score(&c, demote))
-            .collect()
+    fn explain(&self, hand: &str) -> String {
+        let Evaluation { category, tiebreak } = self.evaluate(hand);
+        let (groups, n_wild) = self.groups(hand);
+
+        let wild = match groups.first() {
+            _ if n_wild == 0 => String::new(),
+            Some((c, _)) => format!(", {n_wild} wild as {c}"),
+            None => format!(", {n_wild} wild"),
+        };
+
+        format!(
+            "{hand}: {} (groups {:?}{wild}), tiebreak {:?}",
+            self.categories[category].name,
+            self.group_sizes(hand),
+            tiebreak
+        )
     }
 }
 
@@ -103,29 +148,10 @@ fn parse(input: &str) -> Vec<CamelCard> {
         .collect()
 }
 
-fn sorter(a: &CamelCard, b: &CamelCard, promote: bool) -> Ordering {
-    match a.hand_score(promote).cmp(&b.hand_score(promote)) {
-        Ordering::Equal => {
-            for (s1, s2) in a
-                .card_scores(promote)
-                .iter()
-                .zip(b.card_scores(promote).iter())
-            {
-                match s1.cmp(s2) {
-                    Ordering::Equal => continue,
-                    other => return other,
-                }
-            }
-            Ordering::Equal
-        }
-        other => other,
-    }
-}
-
-fn p1(input: &str) -> usize {
+fn winnings(input: &str, rules: &HandRules) -> usize {
     let mut camel_cards = parse(input);
 
-    camel_cards.sort_by(|a, b| sorter(a, b, false));
+    camel_cards.sort_by_cached_key(|card| rules.evaluate(&card.hand));
 
     camel_cards
         .iter()
@@ -134,16 +160,26 @@ fn p1(input: &str) -> usize {
         .sum()
 }
 
-fn p2(input: &str) -> usize {
+fn explain(input: &str, rules: &HandRules) -> String {
+    // Every hand from weakest to strongest, with its rank.
     let mut camel_cards = parse(input);
 
-    camel_cards.sort_by(|a, b| sorter(a, b, true));
+    camel_cards.sort_by_cached_key(|card| rules.evaluate(&card.hand));
 
     camel_cards
         .iter()
         .enumerate()
-        .map(|(i, card)| card.bid * (i + 1))
-        .sum()
+        .map(|(i, card)| format!("{} {}", i + 1, rules.explain(&card.hand)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn p1(input: &str) -> usize {
+    winnings(input, &HandRules::standard())
+}
+
+fn p2(input: &str) -> usize {
+    winnings(input, &HandRules::jokers())
 }
 
 fn main() {
@@ -157,6 +193,16 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "explain" => {
+            // Optionally followed by the rules to use, standard by default.
+            let rules = match args.get(3).map(|r| r.as_str()) {
+                None | Some("standard") => HandRules::standard(),
+                Some("jokers") => HandRules::jokers(),
+                Some(other) => panic!("Invalid rules {other}"),
+            };
+
+            println!("{}", explain(&input, &rules))
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -176,4 +222,45 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 5905);
     }
+
+    #[test]
+    fn test_all_jokers() {
+        let rules = HandRules::jokers();
+
+        assert_eq!(
+            rules.explain("JJJJJ"),
+            "JJJJJ: Five of a kind (groups [5], 5 wild), tiebreak [0, 0, 0, 0, 0]"
+        );
+        assert!(rules.evaluate("JJJJJ") < rules.evaluate("22222"));
+        assert!(rules.evaluate("JJJJJ") > rules.evaluate("AAAAK"));
+
+        let rules = HandRules::standard();
+        assert_eq!(rules.evaluate("JJJJJ").category, 6);
+        assert!(rules.evaluate("JJJJJ") > rules.evaluate("22222"));
+    }
+
+    #[test]
+    fn test_wildcards() {
+        let rules = HandRules::jokers();
+
+        assert_eq!(
+            rules.explain("KTJJT"),
+            "KTJJT: Four of a kind (groups [4, 1], 2 wild as T), tiebreak [11, 9, 0, 0, 9]"
+        );
+
+        // Deuces wild as well as jokers.
+        let rules = HandRules {
+            wild: vec!['J', '2'],
+            ..HandRules::jokers()
+        };
+
+        assert_eq!(
+            rules.explain("J2345"),
+            "J2345: Three of a kind (groups [3, 1, 1], 2 wild as 5), tiebreak [0, 1, 2, 3, 4]"
+        );
+        assert_eq!(
+            rules.explain("2J2QQ"),
+            "2J2QQ: Five of a kind (groups [5], 3 wild as Q), tiebreak [1, 0, 1, 10, 10]"
+        );
+    }
 }
@@ -1,4 +1,5 @@
 use std::{collections::HashSet, env, fs};
+use utils::geometry::{interior_points, Point};
 
 fn parse(input: &str) -> Vec<Vec<char>> {
     input.lines().map(|l| l.trim().chars().collect()).collect()
@@ -69,16 +70,28 @@ fn next_pos(pos: &Position, tile: &char, seen: &HashSet<Position>) -> Option<Pos
     }
 }
 
-fn get_loop_locations(tiles: &[Vec<char>], start: &Position) -> HashSet<Position> {
+fn get_loop_locations(tiles: &[Vec<char>], start: &Position) -> Vec<Position> {
+    // The tiles of the loop, in the order they are walked.
     let mut seen = HashSet::new();
+    let mut path = Vec::new();
     let mut pos = *start;
 
     while let Some(next_pos) = next_pos(&pos, &tiles[pos.0][pos.1], &seen) {
         seen.insert(next_pos);
+        path.push(next_pos);
         pos = next_pos;
     }
 
-    seen
+    path
+}
+
+fn get_polygon(tiles: &[Vec<char>], start: &Position) -> Vec<Point> {
+    // Every tile of the loop is a vertex of the polygon it forms, taking
+    // columns as x and rows as y.
+    get_loop_locations(tiles, start)
+        .iter()
+        .map(|&(i, j)| (j as i64, i as i64))
+        .collect()
 }
 
 fn p1(input: &str) -> usize {
@@ -91,69 +104,15 @@ fn p1(input: &str) -> usize {
     get_loop_locations(&tiles, &start).len() / 2
 }
 
-fn get_num_inside_tiles_in_row(row: &[char]) -> usize {
-    let mut inside = false;
-    let mut ridin: Option<char> = None;
-    let mut count = 0;
-
-    for c in row {
-        match c {
-            '|' => {
-                inside = !inside;
-            }
-            s @ ('L' | 'F') => {
-                ridin = Some(*s);
-            }
-            e @ ('J' | '7') => {
-                match (ridin, e) {
-                    (Some('L'), '7') | (Some('F'), 'J') => {
-                        inside = !inside;
-                    }
-                    _ => continue,
-                };
-                ridin = None;
-            }
-            '.' if inside => {
-                count += 1;
-            }
-            _ => continue,
-        }
-    }
-
-    count
-}
-
-fn p2(input: &str) -> usize {
+fn p2(input: &str) -> u64 {
     let mut tiles = parse(input);
     let start = get_start_location(&tiles);
     let start_tile = get_starting_tile(&tiles, &start);
 
     tiles[start.0][start.1] = start_tile;
 
-    let loop_locations = get_loop_locations(&tiles, &start);
-
-    // Replace all other locations except the loop with a .
-    let cleaned_tiles: Vec<Vec<char>> = tiles
-        .iter()
-        .enumerate()
-        .map(|(i, row)| {
-            row.iter()
-                .enumerate()
-                .map(|(j, &c)| {
-                    if loop_locations.contains(&(i, j)) {
-                        c
-                    } else {
-                        '.'
-                    }
-                })
-                .collect()
-        })
-        .collect();
-
-    cleaned_tiles
-        .iter()
-        .map(|r| get_num_inside_tiles_in_row(r))
-        .sum()
+    // The inside tiles are the lattice points inside the loop.
+    interior_points(&get_polygon(&tiles, &start)).expect("The loop doesn't enclose anything")
 }
 
 fn main() {
@@ -174,6 +133,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::geometry::{contains, Containment};
 
     const EXAMPLE1: &str = include_str!("../eg1.txt");
     const EXAMPLE2: &str = include_str!("../eg2.txt");
@@ -195,4 +155,23 @@ mod tests {
         assert_eq!(p2(EXAMPLE5), 8);
         assert_eq!(p2(EXAMPLE6), 10);
     }
+
+    #[test]
+    fn test_contains() {
+        // Checking every tile against the loop agrees with Pick's theorem.
+        for example in [EXAMPLE3, EXAMPLE4, EXAMPLE5, EXAMPLE6] {
+            let mut tiles = parse(example);
+            let start = get_start_location(&tiles);
+            tiles[start.0][start.1] = get_starting_tile(&tiles, &start);
+
+            let polygon = get_polygon(&tiles, &start);
+
+            let inside = (0..tiles.len())
+                .flat_map(|i| (0..tiles[0].len()).map(move |j| (j as i64, i as i64)))
+                .filter(|&point| contains(&polygon, point) == Containment::Inside)
+                .count();
+
+            assert_eq!(inside as u64, p2(example));
+        }
+    }
 }
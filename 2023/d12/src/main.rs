@@ -1,5 +1,4 @@
-use cached::{proc_macro::cached, UnboundCache};
-use std::{env, fs};
+use std::{env, fs, iter};
 
 struct Springs {
     record: String,
@@ -26,68 +25,147 @@ fn parse(input: &str) -> Vec<Springs> {
         .collect()
 }
 
-#[cached(
-    ty = "UnboundCache<String, usize>",
-    create = "{UnboundCache::new()}",
-    convert = r#"{format!("{}{:?}", record, sizes)}"#
-)]
-fn count(record: &str, sizes: &[usize]) -> usize {
-    if record.is_empty() {
-        return if sizes.is_empty() { 1 } else { 0 };
-    } else if sizes.is_empty() {
-        return if record.contains('#') { 0 } else { 1 };
-    // If the length of the string is less than the
-    // number of expected '#' + the inbetween '.' characters
-    // then we can't have a valid record
-    } else if record.len() < sizes.iter().sum::<usize>() + sizes.len() - 1 {
-        return 0;
+impl Springs {
+    fn unfold(&self, factor: usize) -> Springs {
+        Springs {
+            record: vec![self.record.as_str(); factor].join("?"),
+            sizes: self.sizes.repeat(factor),
+        }
     }
+}
 
-    let mut counts = 0;
+struct Arrangements<'a> {
+    record: &'a [u8],
+    sizes: &'a [usize],
+    // Number of '.' in `record[..i]`, to check whether a group fits in O(1).
+    operational: Vec<usize>,
+    // table[i][j] is the number of ways `record[i..]` can be arranged into the
+    // groups `sizes[j..]`.
+    table: Vec<Vec<usize>>,
+}
 
-    if record.starts_with('.') || record.starts_with('?') {
-        counts += count(&record[1..], sizes);
+impl<'a> Arrangements<'a> {
+    fn new(record: &'a str, sizes: &'a [usize]) -> Arrangements<'a> {
+        let record = record.as_bytes();
+
+        let operational = iter::once(0)
+            .chain(record.iter().scan(0, |acc, &c| {
+                *acc += usize::from(c == b'.');
+                Some(*acc)
+            }))
+            .collect();
+
+        let mut arrangements = Arrangements {
+            record,
+            sizes,
+            operational,
+            table: Vec::new(),
+        };
+
+        let (n, m) = (record.len(), sizes.len());
+
+        let mut table = vec![vec![0; m + 1]; n + 1];
+        table[n][m] = 1;
+
+        for i in (0..n).rev() {
+            for j in 0..=m {
+                if record[i] != b'#' {
+                    table[i][j] += table[i + 1][j];
+                }
+
+                if record[i] != b'.' && j < m && arrangements.fits(i, arrangements.sizes[j]) {
+                    table[i][j] += table[arrangements.after(i, arrangements.sizes[j])][j + 1];
+                }
+            }
+        }
+
+        arrangements.table = table;
+        arrangements
+    }
+
+    fn fits(&self, i: usize, size: usize) -> bool {
+        // A group of `size` damaged springs can start at `i` if there is no
+        // operational spring inside it, and no damaged one right after it.
+        i + size <= self.record.len()
+            && self.operational[i + size] == self.operational[i]
+            && self.record.get(i + size) != Some(&b'#')
     }
 
-    if record.starts_with('#') || record.starts_with('?') {
-        let n = sizes[0];
-        let (block, rest) = record.split_at(n);
+    fn after(&self, i: usize, size: usize) -> usize {
+        // Where the next group can start, skipping the operational spring
+        // that has to follow a group.
+        (i + size + 1).min(self.record.len())
+    }
 
-        if block.chars().all(|c| c != '.') {
-            if rest.is_empty() {
-                counts += count(rest, &sizes[1..]);
-            } else if !rest.starts_with('#') {
-                counts += count(&rest[1..], &sizes[1..]);
+    fn count(&self) -> usize {
+        self.table[0][0]
+    }
+
+    fn enumerate(&self) -> Vec<String> {
+        // Every valid arrangement, with all the unknowns filled in. The number
+        // of arrangements blows up quickly, so this is only meant for small
+        // rows. Branches without any valid arrangement are never explored.
+        let (n, m) = (self.record.len(), self.sizes.len());
+
+        let mut found = Vec::new();
+        let mut to_visit = vec![(0, 0, Vec::new())];
+
+        while let Some((i, j, prefix)) = to_visit.pop() {
+            if i == n {
+                if j == m {
+                    found.push(String::from_utf8(prefix).unwrap());
+                }
+                continue;
+            }
+
+            if self.record[i] != b'#' && self.table[i + 1][j] > 0 {
+                let mut prefix = prefix.clone();
+                prefix.push(b'.');
+                to_visit.push((i + 1, j, prefix));
+            }
+
+            if self.record[i] != b'.' && j < m && self.fits(i, self.sizes[j]) {
+                let next = self.after(i, self.sizes[j]);
+
+                if self.table[next][j + 1] > 0 {
+                    let mut prefix = prefix;
+                    prefix.extend(iter::repeat_n(b'#', self.sizes[j]));
+                    prefix.extend(iter::repeat_n(b'.', next - i - self.sizes[j]));
+                    to_visit.push((next, j + 1, prefix));
+                }
             }
         }
+
+        found.sort();
+        found
     }
+}
 
-    counts
+fn count(springs: &Springs) -> usize {
+    Arrangements::new(&springs.record, &springs.sizes).count()
 }
 
-fn p1(input: &str) -> usize {
-    let spring_field = parse(input);
-    spring_field
+fn arrangements(input: &str) -> String {
+    parse(input)
         .iter()
-        .map(|s| count(&s.record, &s.sizes))
-        .sum()
+        .map(|s| {
+            let found = Arrangements::new(&s.record, &s.sizes).enumerate();
+            format!("{}:\n{}", s.record, found.join("\n"))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn total(input: &str, factor: usize) -> usize {
+    parse(input).iter().map(|s| count(&s.unfold(factor))).sum()
+}
+
+fn p1(input: &str) -> usize {
+    total(input, 1)
 }
 
 fn p2(input: &str) -> usize {
-    let spring_field = parse(input);
-    spring_field
-        .iter()
-        .map(
-            |Springs {
-                 record: r,
-                 sizes: s,
-             }| Springs {
-                record: format!("{}?{}?{}?{}?{}", r, r, r, r, r),
-                sizes: s.iter().cycle().take(s.len() * 5).copied().collect(),
-            },
-        )
-        .map(|s| count(&s.record, &s.sizes))
-        .sum()
+    total(input, 5)
 }
 
 fn main() {
@@ -101,6 +179,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "arrangements" => println!("{}", arrangements(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -120,4 +199,34 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 525152);
     }
+
+    #[test]
+    fn test_unfold() {
+        let counts: Vec<usize> = parse(EXAMPLE).iter().map(|s| count(&s.unfold(5))).collect();
+
+        assert_eq!(counts, vec![1, 16384, 1, 16, 2500, 506250]);
+        assert_eq!(total(EXAMPLE, 1), p1(EXAMPLE));
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let arrangements = Arrangements::new("?###????????", &[3, 2, 1]);
+
+        assert_eq!(arrangements.count(), 10);
+        assert_eq!(
+            arrangements.enumerate(),
+            vec![
+                ".###.##.#...",
+                ".###.##..#..",
+                ".###.##...#.",
+                ".###.##....#",
+                ".###..##.#..",
+                ".###..##..#.",
+                ".###..##...#",
+                ".###...##.#.",
+                ".###...##..#",
+                ".###....##.#",
+            ]
+        );
+    }
 }
@@ -1,86 +1,146 @@
 use std::{env, fs};
 
-type Pattern = Vec<Vec<char>>;
+// Each row and each column as a bitmask, with a bit set for every rock.
+struct Pattern {
+    rows: Vec<u64>,
+    cols: Vec<u64>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Reflection {
+    // Between this many rows and the rest.
+    Horizontal(usize),
+    // Between this many columns and the rest.
+    Vertical(usize),
+}
+
+#[derive(Debug, PartialEq)]
+struct Mirror {
+    reflection: Reflection,
+    // The cells that have to be fixed for the reflection to be perfect. Each
+    // is on the top or left side, although fixing its mirror image instead
+    // would do just as well.
+    smudges: Vec<(usize, usize)>,
+}
 
 fn parse(input: &str) -> Vec<Pattern> {
     input
         .split("\n\n")
-        .map(|block| block.lines().map(|line| line.chars().collect()).collect())
+        .map(|block| {
+            let lines: Vec<&[u8]> = block.lines().map(|line| line.as_bytes()).collect();
+            assert!(lines.len() <= 64 && lines[0].len() <= 64, "Pattern too big");
+
+            let mask = |cells: &mut dyn Iterator<Item = u8>| {
+                cells
+                    .enumerate()
+                    .filter(|&(_, c)| c == b'#')
+                    .fold(0, |acc, (i, _)| acc | (1 << i))
+            };
+
+            Pattern {
+                rows: lines
+                    .iter()
+                    .map(|line| mask(&mut line.iter().copied()))
+                    .collect(),
+                cols: (0..lines[0].len())
+                    .map(|j| mask(&mut lines.iter().map(|line| line[j])))
+                    .collect(),
+            }
+        })
         .collect()
 }
 
-fn transpose(pattern: &Pattern) -> Pattern {
-    let mut transposed = vec![vec![' '; pattern.len()]; pattern[0].len()];
-
-    for (i, row) in pattern.iter().enumerate() {
-        for (j, &ch) in row.iter().enumerate() {
-            transposed[j][i] = ch;
-        }
-    }
-
-    transposed
+fn reflect(lines: &[u64], smudges: u32) -> Option<usize> {
+    // The first line of reflection where exactly `smudges` cells disagree
+    // with their mirror images.
+    (1..lines.len()).find(|&i| {
+        lines[..i]
+            .iter()
+            .rev()
+            .zip(&lines[i..])
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum::<u32>()
+            == smudges
+    })
 }
 
-fn count_disagreements(first_half: &[Vec<char>], second_half: &[Vec<char>]) -> usize {
-    let mut count = 0;
-
-    for (row_first, row_second) in first_half.iter().rev().zip(second_half.iter()) {
-        for (a, b) in row_first.iter().zip(row_second.iter()) {
-            if a != b {
-                count += 1;
-            }
-        }
-    }
-
-    count
+fn differences(lines: &[u64], i: usize) -> Vec<(usize, usize)> {
+    // (line, position) of each cell before i that disagrees with its mirror.
+    lines[..i]
+        .iter()
+        .enumerate()
+        .rev()
+        .zip(&lines[i..])
+        .flat_map(|((line, a), b)| {
+            let diff = a ^ b;
+            (0..64)
+                .filter(move |bit| diff & (1 << bit) != 0)
+                .map(move |bit| (line, bit))
+        })
+        .collect()
 }
 
-fn find_reflection(pattern: &Pattern, smudge: bool) -> Option<usize> {
-    for i in 1..pattern.len() {
-        let (first_half, second_half) = pattern.split_at(i);
-
-        match count_disagreements(first_half, second_half) {
-            0 if !smudge => return Some(i),
-            1 if smudge => return Some(i),
-            _ => continue,
+impl Pattern {
+    fn mirror(&self, smudges: u32) -> Option<Mirror> {
+        if let Some(i) = reflect(&self.rows, smudges) {
+            let mut cells = differences(&self.rows, i);
+            cells.sort();
+
+            Some(Mirror {
+                reflection: Reflection::Horizontal(i),
+                smudges: cells,
+            })
+        } else {
+            let j = reflect(&self.cols, smudges)?;
+            let mut cells: Vec<_> = differences(&self.cols, j)
+                .into_iter()
+                .map(|(col, row)| (row, col))
+                .collect();
+            cells.sort();
+
+            Some(Mirror {
+                reflection: Reflection::Vertical(j),
+                smudges: cells,
+            })
         }
     }
-
-    None
 }
 
-fn p1(input: &str) -> usize {
-    let patterns = parse(input);
-
-    patterns
+fn summarize(input: &str, smudges: u32) -> usize {
+    parse(input)
         .iter()
-        .map(|pattern| {
-            if let Some(i) = find_reflection(pattern, false) {
-                100 * i
-            } else if let Some(i) = find_reflection(&transpose(pattern), false) {
-                i
-            } else {
-                panic!("Invalid pattern");
-            }
+        .map(|pattern| match pattern.mirror(smudges) {
+            Some(Mirror {
+                reflection: Reflection::Horizontal(i),
+                ..
+            }) => 100 * i,
+            Some(Mirror {
+                reflection: Reflection::Vertical(j),
+                ..
+            }) => j,
+            None => panic!("Invalid pattern"),
         })
         .sum()
 }
 
-fn p2(input: &str) -> usize {
-    let patterns = parse(input);
-
-    patterns
+fn report(input: &str, smudges: u32) -> String {
+    parse(input)
         .iter()
-        .map(|pattern| {
-            if let Some(i) = find_reflection(pattern, true) {
-                100 * i
-            } else if let Some(i) = find_reflection(&transpose(pattern), true) {
-                i
-            } else {
-                panic!("Invalid pattern");
-            }
+        .enumerate()
+        .map(|(n, pattern)| match pattern.mirror(smudges) {
+            Some(mirror) => format!("{n}: {:?}, smudged {:?}", mirror.reflection, mirror.smudges),
+            None => format!("{n}: no reflection"),
         })
-        .sum()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn p1(input: &str) -> usize {
+    summarize(input, 0)
+}
+
+fn p2(input: &str) -> usize {
+    summarize(input, 1)
 }
 
 fn main() {
@@ -94,6 +154,11 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "report" => {
+            // Optionally followed by the number of smudges, one by default.
+            let smudges = args.get(3).map_or(1, |k| k.parse().unwrap());
+            println!("{}", report(&input, smudges))
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -113,4 +178,77 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 400);
     }
+
+    fn mirrors(smudges: u32) -> Vec<Option<Mirror>> {
+        parse(EXAMPLE).iter().map(|p| p.mirror(smudges)).collect()
+    }
+
+    #[test]
+    fn test_smudges() {
+        use Reflection::*;
+
+        assert_eq!(
+            mirrors(0),
+            [
+                Some(Mirror {
+                    reflection: Vertical(5),
+                    smudges: vec![]
+                }),
+                Some(Mirror {
+                    reflection: Horizontal(4),
+                    smudges: vec![]
+                }),
+            ]
+        );
+
+        assert_eq!(
+            mirrors(1),
+            [
+                Some(Mirror {
+                    reflection: Horizontal(3),
+                    smudges: vec![(0, 0)]
+                }),
+                Some(Mirror {
+                    reflection: Horizontal(1),
+                    smudges: vec![(0, 4)]
+                }),
+            ]
+        );
+
+        assert_eq!(
+            mirrors(2),
+            [
+                Some(Mirror {
+                    reflection: Vertical(1),
+                    smudges: vec![(0, 0), (6, 0)]
+                }),
+                Some(Mirror {
+                    reflection: Vertical(7),
+                    smudges: vec![(2, 5), (5, 5)]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fixing_smudges() {
+        // Flipping the reported cells makes the reflection perfect.
+        for smudges in 0..4 {
+            for mut pattern in parse(EXAMPLE) {
+                let Some(mirror) = pattern.mirror(smudges) else {
+                    continue;
+                };
+
+                for &(row, col) in &mirror.smudges {
+                    pattern.rows[row] ^= 1 << col;
+                    pattern.cols[col] ^= 1 << row;
+                }
+
+                match mirror.reflection {
+                    Reflection::Horizontal(i) => assert!(differences(&pattern.rows, i).is_empty()),
+                    Reflection::Vertical(j) => assert!(differences(&pattern.cols, j).is_empty()),
+                }
+            }
+        }
+    }
 }
@@ -1,23 +1,10 @@
-use std::{collections::HashMap, env, fs};
-
-type Position = (usize, usize);
-
-struct Map {
-    grid: Vec<Vec<char>>,
-    n_rows: usize,
-    n_cols: usize,
-}
-
-fn parse(input: &str) -> Map {
-    let grid: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
-
-    Map {
-        n_rows: grid.len(),
-        n_cols: grid[0].len(),
-        grid,
-    }
-}
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env, fs,
+    hash::{Hash, Hasher},
+};
 
+#[derive(Debug, Clone, Copy)]
 enum Direction {
     North,
     West,
@@ -27,134 +14,192 @@ enum Direction {
 
 use Direction::*;
 
-fn step(map: &mut Map, (i, j): Position, direction: &Direction) -> Option<Position> {
-    let c = map.grid[i][j];
-
-    let (ni, nj) = match direction {
-        North => {
-            if i == 0 {
-                return None;
-            }
-
-            (i - 1, j)
-        }
-        West => {
-            if j == 0 {
-                return None;
-            }
-            (i, j - 1)
-        }
-        South => {
-            if i == map.n_rows - 1 {
-                return None;
-            }
-
-            (i + 1, j)
-        }
-        East => {
-            if j == map.n_cols - 1 {
-                return None;
-            }
-            (i, j + 1)
-        }
-    };
-
-    match map.grid[ni][nj] {
-        '.' => {
-            map.grid[ni][nj] = c;
-            map.grid[i][j] = '.';
-
-            Some((ni, nj))
-        }
-        'O' => {
-            if step(map, (ni, nj), direction).is_some() {
-                step(map, (i, j), direction)
-            } else {
-                None
-            }
+impl Direction {
+    fn from(c: char) -> Direction {
+        match c {
+            'N' => North,
+            'W' => West,
+            'S' => South,
+            'E' => East,
+            c => panic!("Invalid direction {c}"),
         }
-        _ => None,
     }
 }
 
-fn tilt(map: &mut Map, pos: Position, direction: &Direction) -> Position {
-    let mut pos = pos;
+// The rocks on the platform as bitmasks, both per row (bit j is column j) and
+// per column (bit i is row i), so a tilt always works on whole lines.
+#[derive(Clone)]
+struct Platform {
+    n_rows: usize,
+    n_cols: usize,
+    round_rows: Vec<u128>,
+    cube_rows: Vec<u128>,
+    round_cols: Vec<u128>,
+    cube_cols: Vec<u128>,
+}
 
-    while let Some(next_pos) = step(map, pos, direction) {
-        pos = next_pos;
+fn span(lo: usize, hi: usize) -> u128 {
+    // The bits lo..hi.
+    match hi - lo {
+        128 => u128::MAX,
+        n => ((1 << n) - 1) << lo,
     }
+}
 
-    pos
+fn transpose(lines: &[u128], n: usize) -> Vec<u128> {
+    (0..n)
+        .map(|j| {
+            lines
+                .iter()
+                .enumerate()
+                .filter(|&(_, line)| line & (1 << j) != 0)
+                .fold(0, |acc, (i, _)| acc | (1 << i))
+        })
+        .collect()
 }
 
-fn get_load(map: &Map) -> usize {
-    let mut load = 0;
+fn settle(round: u128, cube: u128, len: usize, towards_start: bool) -> u128 {
+    // Round rocks gather at one end of each run of cells between cube rocks,
+    // so only the number of them in each run matters.
+    let mut settled = 0;
+    let mut start = 0;
 
-    for i in 0..map.n_rows {
-        for j in 0..map.n_cols {
-            if map.grid[i][j] == 'O' {
-                load += map.n_rows - i;
-            }
-        }
+    while start < len {
+        let end = len.min(start + (cube >> start).trailing_zeros() as usize);
+        let count = (round & span(start, end)).count_ones() as usize;
+
+        settled |= if towards_start {
+            span(start, start + count)
+        } else {
+            span(end - count, end)
+        };
+
+        start = end + 1;
     }
 
-    load
+    settled
 }
 
-fn p1(input: &str) -> usize {
-    let mut map = parse(input);
-
-    for i in 0..map.n_rows {
-        for j in 0..map.n_cols {
-            if map.grid[i][j] == 'O' {
-                let _ = tilt(&mut map, (i, j), &North);
-            }
+impl Platform {
+    fn from(input: &str) -> Self {
+        let rows: Vec<&str> = input.lines().collect();
+        let (n_rows, n_cols) = (rows.len(), rows[0].len());
+        assert!(n_rows <= 128 && n_cols <= 128, "The platform is too big");
+
+        let mask = |rock| -> Vec<u128> {
+            rows.iter()
+                .map(|row| {
+                    row.chars()
+                        .enumerate()
+                        .filter(|&(_, c)| c == rock)
+                        .fold(0, |acc, (j, _)| acc | (1 << j))
+                })
+                .collect()
+        };
+
+        let (round_rows, cube_rows) = (mask('O'), mask('#'));
+
+        Platform {
+            n_rows,
+            n_cols,
+            round_cols: transpose(&round_rows, n_cols),
+            cube_cols: transpose(&cube_rows, n_cols),
+            round_rows,
+            cube_rows,
         }
     }
 
-    get_load(&map)
-}
-
-fn cycle(map: &mut Map) {
-    for dir in [North, West, South, East] {
-        for i in 0..map.n_rows {
-            for j in 0..map.n_cols {
-                if map.grid[i][j] == 'O' {
-                    let _ = tilt(map, (i, j), &dir);
+    fn tilt(&mut self, direction: Direction) {
+        match direction {
+            North | South => {
+                for (round, &cube) in self.round_cols.iter_mut().zip(&self.cube_cols) {
+                    *round = settle(*round, cube, self.n_rows, matches!(direction, North));
+                }
+                self.round_rows = transpose(&self.round_cols, self.n_rows);
+            }
+            West | East => {
+                for (round, &cube) in self.round_rows.iter_mut().zip(&self.cube_rows) {
+                    *round = settle(*round, cube, self.n_cols, matches!(direction, West));
                 }
+                self.round_cols = transpose(&self.round_rows, self.n_cols);
             }
         }
     }
+
+    fn load(&self) -> usize {
+        self.round_rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| row.count_ones() as usize * (self.n_rows - i))
+            .sum()
+    }
+
+    fn fingerprint(&self) -> u64 {
+        // The cube rocks never move, so the round ones are the whole state.
+        let mut hasher = DefaultHasher::new();
+        self.round_rows.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn render(&self) -> String {
+        (0..self.n_rows)
+            .map(|i| {
+                (0..self.n_cols)
+                    .map(
+                        |j| match (self.round_rows[i] >> j & 1, self.cube_rows[i] >> j & 1) {
+                            (1, _) => 'O',
+                            (_, 1) => '#',
+                            _ => '.',
+                        },
+                    )
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
-fn p2(input: &str) -> usize {
-    let mut map = parse(input);
+fn spin(input: &str, sequence: &str, times: usize) -> Platform {
+    // Applies the tilts in `sequence`, `times` times over. The platform ends
+    // up repeating itself, so the rest can be skipped once it does.
+    let mut platform = Platform::from(input);
+    let directions: Vec<Direction> = sequence.chars().map(Direction::from).collect();
+
+    let mut history = vec![platform.round_rows.clone()];
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::from([(platform.fingerprint(), vec![0])]);
+
+    for i in 1..=times {
+        for &direction in &directions {
+            platform.tilt(direction);
+        }
 
-    let mut i = 0;
-    let mut seen: HashMap<Vec<Vec<char>>, usize> = HashMap::new();
+        let fingerprint = platform.fingerprint();
+        let previous = seen.entry(fingerprint).or_default();
+
+        // Fingerprints can collide, so check the rocks themselves too.
+        if let Some(&start) = previous
+            .iter()
+            .find(|&&j| history[j] == platform.round_rows)
+        {
+            let end = start + (times - start) % (i - start);
+            platform.round_rows = history[end].clone();
+            platform.round_cols = transpose(&platform.round_rows, platform.n_cols);
+            break;
+        }
 
-    while !seen.contains_key(&map.grid) {
-        seen.insert(map.grid.clone(), i);
-        cycle(&mut map);
-        i += 1;
+        previous.push(i);
+        history.push(platform.round_rows.clone());
     }
 
-    let cycle_start = seen.get(&map.grid).unwrap();
-    let cycle_end = i;
-    let final_grid = (1000000000 - cycle_start) % (cycle_end - cycle_start) + cycle_start;
+    platform
+}
 
-    map.grid = seen
-        .iter()
-        .find_map(|(key, &val)| {
-            if val == final_grid {
-                Some(key.clone())
-            } else {
-                None
-            }
-        })
-        .unwrap();
+fn p1(input: &str) -> usize {
+    spin(input, "N", 1).load()
+}
 
-    get_load(&map)
+fn p2(input: &str) -> usize {
+    spin(input, "NWSE", 1000000000).load()
 }
 
 fn main() {
@@ -168,6 +213,13 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "render" => {
+            // Optionally followed by a sequence of tilts and how many times
+            // to apply it, one full cycle by default.
+            let sequence = args.get(3).map_or("NWSE", |s| s.as_str());
+            let times = args.get(4).map_or(1, |n| n.parse().unwrap());
+            println!("{}", spin(&input, sequence, times).render())
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -175,6 +227,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::rng::Rng;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -187,4 +240,113 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 64);
     }
+
+    #[test]
+    fn test_sequences() {
+        // A single tilt is the same however many times it's repeated.
+        assert_eq!(spin(EXAMPLE, "N", 1000).load(), 136);
+
+        // From the puzzle, after three cycles.
+        assert_eq!(
+            spin(EXAMPLE, "NWSE", 3).render(),
+            ".....#....\n\
+             ....#...O#\n\
+             .....##...\n\
+             ..O#......\n\
+             .....OOO#.\n\
+             .O#...O#.#\n\
+             ....O#...O\n\
+             .......OOO\n\
+             #...O###.O\n\
+             #.OOO#...O"
+        );
+
+        // Two cycles at a time, half as often.
+        assert_eq!(spin(EXAMPLE, "NWSENWSE", 500000000).load(), 64);
+
+        // Every cycle up to and past the repetition agrees with moving rocks
+        // one step at a time.
+        let mut grid = to_grid(EXAMPLE);
+        for times in 1..=20 {
+            for direction in [North, West, South, East] {
+                tilt_slowly(&mut grid, direction);
+            }
+            assert_eq!(spin(EXAMPLE, "NWSE", times).render(), from_grid(&grid));
+        }
+    }
+
+    fn to_grid(input: &str) -> Vec<Vec<char>> {
+        input.lines().map(|l| l.chars().collect()).collect()
+    }
+
+    fn from_grid(grid: &[Vec<char>]) -> String {
+        let rows: Vec<String> = grid.iter().map(|row| row.iter().collect()).collect();
+        rows.join("\n")
+    }
+
+    fn tilt_slowly(grid: &mut [Vec<char>], direction: Direction) {
+        // Keep moving any rock that can roll by one step until none can.
+        let (di, dj) = match direction {
+            North => (-1, 0),
+            West => (0, -1),
+            South => (1, 0),
+            East => (0, 1),
+        };
+        let (n_rows, n_cols) = (grid.len() as isize, grid[0].len() as isize);
+
+        let mut moved = true;
+        while moved {
+            moved = false;
+
+            for i in 0..n_rows {
+                for j in 0..n_cols {
+                    let (ni, nj) = (i + di, j + dj);
+                    if !(0..n_rows).contains(&ni) || !(0..n_cols).contains(&nj) {
+                        continue;
+                    }
+
+                    let [i, j, ni, nj] = [i, j, ni, nj].map(|x| x as usize);
+                    if grid[i][j] == 'O' && grid[ni][nj] == '.' {
+                        grid[i][j] = '.';
+                        grid[ni][nj] = 'O';
+                        moved = true;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_differential() {
+        let mut rng = Rng::new(14);
+
+        for _ in 0..50 {
+            let (n_rows, n_cols) = (1 + rng.below(20), 1 + rng.below(20));
+
+            let input = (0..n_rows)
+                .map(|_| {
+                    (0..n_cols)
+                        .map(|_| rng.pick(&['.', '.', 'O', '#']))
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            let sequence: Vec<Direction> = (0..1 + rng.below(8))
+                .map(|_| rng.pick(&[North, West, South, East]))
+                .collect();
+
+            let mut grid = to_grid(&input);
+            let mut platform = Platform::from(&input);
+
+            for _ in 0..3 {
+                for &direction in &sequence {
+                    tilt_slowly(&mut grid, direction);
+                    platform.tilt(direction);
+                }
+
+                assert_eq!(platform.render(), from_grid(&grid));
+            }
+        }
+    }
 }
@@ -1,7 +1,28 @@
-use std::{
-    collections::{HashMap, HashSet},
-    env, fs,
-};
+use rayon::prelude::*;
+use std::{env, fs};
+use utils::bitset::BitSet;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dir {
+    Up,
+    Right,
+    Down,
+    Left,
+}
+
+use Dir::*;
+
+impl Dir {
+    fn from(c: char) -> Dir {
+        match c {
+            '^' => Up,
+            '>' => Right,
+            'v' => Down,
+            '<' => Left,
+            c => panic!("Invalid direction {c}"),
+        }
+    }
+}
 
 struct Map {
     grid: Vec<Vec<char>>,
@@ -9,8 +30,8 @@ struct Map {
     n_cols: usize,
 }
 
-type Position = (usize, usize);
-type Visited = HashMap<Position, HashSet<char>>;
+// A beam in a cell, heading in a direction, packed as cell * 4 + direction.
+type State = usize;
 
 fn parse(input: &str) -> Map {
     let grid: Vec<Vec<char>> = input.lines().map(|l| l.trim().chars().collect()).collect();
@@ -22,79 +43,256 @@ fn parse(input: &str) -> Map {
     }
 }
 
-fn travel(map: &Map, vector: (usize, usize, char)) -> usize {
-    let mut visited: Visited = HashMap::new();
-    let mut to_visit = vec![vector];
+impl Map {
+    fn state(&self, (i, j, d): (usize, usize, Dir)) -> State {
+        (i * self.n_cols + j) * 4 + d as usize
+    }
 
-    while let Some((i, j, d)) = to_visit.pop() {
-        if let Some(dirs) = visited.get_mut(&(i, j)) {
-            if dirs.contains(&d) {
-                continue;
-            } else {
-                dirs.insert(d);
+    fn step(&self, cell: usize, d: Dir) -> Option<State> {
+        let (i, j) = (cell / self.n_cols, cell % self.n_cols);
+
+        let next = match d {
+            Up if i > 0 => (i - 1, j, d),
+            Right if j < self.n_cols - 1 => (i, j + 1, d),
+            Down if i < self.n_rows - 1 => (i + 1, j, d),
+            Left if j > 0 => (i, j - 1, d),
+            _ => return None,
+        };
+
+        Some(self.state(next))
+    }
+
+    fn successors(&self, state: State) -> [Option<State>; 2] {
+        let cell = state / 4;
+        let d = [Up, Right, Down, Left][state % 4];
+
+        let (a, b) = match (self.grid[cell / self.n_cols][cell % self.n_cols], d) {
+            ('|', Left | Right) => (Up, Some(Down)),
+            ('-', Up | Down) => (Left, Some(Right)),
+            ('/', Right) | ('\\', Left) => (Up, None),
+            ('/', Up) | ('\\', Down) => (Right, None),
+            ('/', Left) | ('\\', Right) => (Down, None),
+            ('/', Down) | ('\\', Up) => (Left, None),
+            _ => (d, None),
+        };
+
+        [self.step(cell, a), b.and_then(|b| self.step(cell, b))]
+    }
+
+    fn entries(&self) -> Vec<State> {
+        let rows = (0..self.n_rows).flat_map(|r| [(r, 0, Right), (r, self.n_cols - 1, Left)]);
+        let cols = (0..self.n_cols).flat_map(|c| [(0, c, Down), (self.n_rows - 1, c, Up)]);
+
+        rows.chain(cols).map(|entry| self.state(entry)).collect()
+    }
+}
+
+// The beam states condensed into their strongly connected components: every
+// state in a component reaches the same cells, so they only need to be found
+// once.
+struct Beams {
+    n_cells: usize,
+    component: Vec<usize>,
+    // The cells and the successor components of each component.
+    cells: Vec<Vec<usize>>,
+    successors: Vec<Vec<usize>>,
+    // The energised cells of the components several entries or components
+    // lead into.
+    shared: Vec<Option<BitSet>>,
+}
+
+impl Beams {
+    fn new(map: &Map, entries: &[State]) -> Self {
+        let n_cells = map.n_rows * map.n_cols;
+        let component = Beams::components(map);
+        let n_components = component.iter().max().map_or(0, |&c| c + 1);
+
+        let mut cells = vec![Vec::new(); n_components];
+        let mut successors = vec![Vec::new(); n_components];
+        let mut in_degree = vec![0; n_components];
+
+        for (state, &c) in component.iter().enumerate() {
+            cells[c].push(state / 4);
+
+            for next in map.successors(state).into_iter().flatten() {
+                let n = component[next];
+                if n != c && !successors[c].contains(&n) {
+                    successors[c].push(n);
+                    in_degree[n] += 1;
+                }
             }
-        } else {
-            visited.insert((i, j), HashSet::from([d]));
         }
 
-        let c = map.grid[i][j];
-
-        match (c, d) {
-            ('.' | '-', '<') if j > 0 => to_visit.push((i, j - 1, d)),
-            ('.' | '|', 'v') if i < map.n_rows - 1 => to_visit.push((i + 1, j, d)),
-            ('.' | '|', '^') if i > 0 => to_visit.push((i - 1, j, d)),
-            ('.' | '-', '>') if j < map.n_cols - 1 => to_visit.push((i, j + 1, d)),
-            ('\\', '<') | ('/', '>') if i > 0 => to_visit.push((i - 1, j, '^')),
-            ('\\', 'v') | ('/', '^') if j < map.n_cols - 1 => to_visit.push((i, j + 1, '>')),
-            ('\\', '^') | ('/', 'v') if j > 0 => to_visit.push((i, j - 1, '<')),
-            ('\\', '>') | ('/', '<') if i < map.n_rows - 1 => to_visit.push((i + 1, j, 'v')),
-            ('|', '<' | '>') => {
-                if i > 0 {
-                    to_visit.push((i - 1, j, '^'));
+        for &entry in entries {
+            in_degree[component[entry]] += 1;
+        }
+
+        let mut beams = Beams {
+            n_cells,
+            component,
+            cells,
+            successors,
+            shared: vec![None; n_components],
+        };
+
+        // Components are numbered so that successors come first, so anything
+        // a shared component leads to has already been worked out.
+        for (c, &degree) in in_degree.iter().enumerate() {
+            if degree > 1 {
+                beams.shared[c] = Some(beams.collect(c));
+            }
+        }
+
+        beams
+    }
+
+    fn components(map: &Map) -> Vec<usize> {
+        // Tarjan's algorithm, without recursion. A component gets its number
+        // once all the components it leads to have theirs.
+        let n_states = map.n_rows * map.n_cols * 4;
+
+        let mut index = vec![usize::MAX; n_states];
+        let mut low = vec![0; n_states];
+        let mut on_stack = BitSet::new(n_states);
+        let mut stack = Vec::new();
+        let mut component = vec![usize::MAX; n_states];
+        let (mut n_visited, mut n_components) = (0, 0);
+
+        for root in 0..n_states {
+            if index[root] != usize::MAX {
+                continue;
+            }
+
+            let mut work = vec![(root, 0)];
+            index[root] = n_visited;
+            low[root] = n_visited;
+            n_visited += 1;
+            stack.push(root);
+            on_stack.insert(root);
+
+            while let Some((v, i)) = work.last_mut() {
+                let v = *v;
+
+                if *i < 2 {
+                    let next = map.successors(v)[*i];
+                    *i += 1;
+
+                    match next {
+                        Some(w) if index[w] == usize::MAX => {
+                            index[w] = n_visited;
+                            low[w] = n_visited;
+                            n_visited += 1;
+                            stack.push(w);
+                            on_stack.insert(w);
+                            work.push((w, 0));
+                        }
+                        Some(w) if on_stack.contains(w) => low[v] = low[v].min(index[w]),
+                        _ => (),
+                    }
+
+                    continue;
+                }
+
+                work.pop();
+                if let Some(&(u, _)) = work.last() {
+                    low[u] = low[u].min(low[v]);
                 }
-                if i < map.n_rows - 1 {
-                    to_visit.push((i + 1, j, 'v'));
+
+                if low[v] == index[v] {
+                    while let Some(w) = stack.pop() {
+                        on_stack.remove(w);
+                        component[w] = n_components;
+                        if w == v {
+                            break;
+                        }
+                    }
+                    n_components += 1;
                 }
             }
-            ('-', '^' | 'v') => {
-                if j > 0 {
-                    to_visit.push((i, j - 1, '<'));
-                }
-                if j < map.n_cols - 1 {
-                    to_visit.push((i, j + 1, '>'));
+        }
+
+        component
+    }
+
+    fn collect(&self, start: usize) -> BitSet {
+        let mut energised = BitSet::new(self.n_cells);
+        let mut seen = BitSet::new(self.cells.len());
+        let mut to_visit = vec![start];
+        seen.insert(start);
+
+        while let Some(c) = to_visit.pop() {
+            if let Some(cells) = self.shared[c].as_ref().filter(|_| c != start) {
+                energised.union_with(cells);
+                continue;
+            }
+
+            for &cell in &self.cells[c] {
+                energised.insert(cell);
+            }
+
+            for &n in &self.successors[c] {
+                if seen.insert(n) {
+                    to_visit.push(n);
                 }
             }
-            _ => continue,
         }
+
+        energised
     }
 
-    visited.len()
+    fn energised(&self, entry: State) -> BitSet {
+        let c = self.component[entry];
+
+        match &self.shared[c] {
+            Some(cells) => cells.clone(),
+            None => self.collect(c),
+        }
+    }
+}
+
+fn render(map: &Map, entry: (usize, usize, Dir), colour: bool) -> String {
+    let beams = Beams::new(map, &[map.state(entry)]);
+    let energised = beams.energised(map.state(entry));
+
+    map.grid
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.iter()
+                .enumerate()
+                .map(|(j, &c)| {
+                    let lit = energised.contains(i * map.n_cols + j);
+
+                    match (colour, lit) {
+                        (false, true) => "#".to_string(),
+                        (false, false) => ".".to_string(),
+                        (true, true) => format!("\x1b[33m{c}\x1b[0m"),
+                        (true, false) => c.to_string(),
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 fn p1(input: &str) -> usize {
     let map = parse(input);
+    let entry = map.state((0, 0, Right));
 
-    travel(&map, (0, 0, '>'))
+    Beams::new(&map, &[entry]).energised(entry).len()
 }
 
 fn p2(input: &str) -> usize {
     let map = parse(input);
+    let entries = map.entries();
+    let beams = Beams::new(&map, &entries);
 
-    let mut result = 0;
-
-    for r in 0..map.n_rows {
-        for (c, d) in [(0, '>'), (map.n_cols - 1, '<')] {
-            result = result.max(travel(&map, (r, c, d)));
-        }
-    }
-
-    for c in 0..map.n_cols {
-        for (r, d) in [(0, 'v'), (map.n_rows - 1, '^')] {
-            result = result.max(travel(&map, (r, c, d)));
-        }
-    }
-
-    result
+    entries
+        .par_iter()
+        .map(|&entry| beams.energised(entry).len())
+        .max()
+        .unwrap()
 }
 
 fn main() {
@@ -105,9 +303,22 @@ fn main() {
 
     let input = fs::read_to_string(filepath).unwrap();
 
+    // The renderings start from the top left, heading right, unless given a
+    // row, a column and a direction (one of ^>v<).
+    let entry = match &args[3..] {
+        [row, col, dir] => (
+            row.parse().unwrap(),
+            col.parse().unwrap(),
+            Dir::from(dir.chars().next().unwrap()),
+        ),
+        _ => (0, 0, Right),
+    };
+
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "render" => println!("{}", render(&parse(&input), entry, false)),
+        "colour" => println!("{}", render(&parse(&input), entry, true)),
         _ => panic!("Invalid part"),
     };
 }
@@ -127,4 +338,51 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 51);
     }
+
+    #[test]
+    fn test_render() {
+        let map = parse(EXAMPLE);
+
+        assert_eq!(
+            render(&map, (0, 0, Right), false),
+            "######....\n\
+             .#...#....\n\
+             .#...#####\n\
+             .#...##...\n\
+             .#...##...\n\
+             .#...##...\n\
+             .#..####..\n\
+             ########..\n\
+             .#######..\n\
+             .#...#.#.."
+        );
+    }
+
+    fn flood(map: &Map, entry: State) -> usize {
+        let mut seen = BitSet::new(map.n_rows * map.n_cols * 4);
+        let mut cells = BitSet::new(map.n_rows * map.n_cols);
+        let mut to_visit = vec![entry];
+
+        while let Some(state) = to_visit.pop() {
+            if seen.insert(state) {
+                cells.insert(state / 4);
+                to_visit.extend(map.successors(state).into_iter().flatten());
+            }
+        }
+
+        cells.len()
+    }
+
+    #[test]
+    fn test_every_entry() {
+        // Sharing cells between components gives the same result as following
+        // each beam separately.
+        let map = parse(EXAMPLE);
+        let entries = map.entries();
+        let beams = Beams::new(&map, &entries);
+
+        for entry in entries {
+            assert_eq!(beams.energised(entry).len(), flood(&map, entry));
+        }
+    }
 }
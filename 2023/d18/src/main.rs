@@ -1,31 +1,21 @@
 use itertools::Itertools;
-use std::{env, fs, ops::Div};
-
-#[derive(Debug, Clone, Copy)]
-enum Direction {
-    Left(usize),
-    Down(usize),
-    Up(usize),
-    Right(usize),
-}
-
-use Direction as Dir;
-
-impl Direction {
-    fn from(dir: char, dist: usize) -> Dir {
-        match dir {
-            'L' => Dir::Left(dist),
-            'D' => Dir::Down(dist),
-            'U' => Dir::Up(dist),
-            'R' => Dir::Right(dist),
-            dir => panic!("Received invalid direction {dir}"),
-        }
+use std::{env, fs};
+use utils::geometry::{lattice_points, vertices, Direction};
+
+fn direction(dir: char) -> Direction {
+    match dir {
+        'L' => Direction::Left,
+        'D' => Direction::Down,
+        'U' => Direction::Up,
+        'R' => Direction::Right,
+        dir => panic!("Received invalid direction {dir}"),
     }
 }
 
 #[derive(Debug)]
 struct Trench {
     direction: Direction,
+    distance: i64,
     color: String,
 }
 
@@ -40,7 +30,8 @@ fn parse(input: &str) -> Vec<Trench> {
                     color.strip_prefix("(#").unwrap().strip_suffix(')').unwrap(),
                 );
                 Trench {
-                    direction: Direction::from(dir, dist),
+                    direction: direction(dir),
+                    distance: dist,
                     color: color.to_string(),
                 }
             } else {
@@ -50,91 +41,32 @@ fn parse(input: &str) -> Vec<Trench> {
         .collect()
 }
 
-type Point = (usize, usize);
-
-fn get_points(directions: &[Direction]) -> Vec<Point> {
-    let mut curr = (0, 0);
-    let mut points = Vec::new();
-
-    for direction in directions {
-        let (x, y) = curr;
-
-        curr = match direction {
-            Dir::Left(d) => (x - d, y),
-            Dir::Down(d) => (x, y + d),
-            Dir::Up(d) => (x, y - d),
-            Dir::Right(d) => (x + d, y),
-        };
-
-        points.push(curr);
-    }
-
-    points
-}
-
-fn area(points: &[Point]) -> usize {
-    // Shoelace algorithm
-    let interior_points = points
-        .iter()
-        .circular_tuple_windows()
-        .map(|(a, b)| {
-            let (x1, y1) = (a.0 as isize, a.1 as isize);
-            let (x2, y2) = (b.0 as isize, b.1 as isize);
-
-            (x1 * y2) - (x2 * y1)
-        })
-        .sum::<isize>()
-        .div(2)
-        .unsigned_abs();
-
-    let boundary_points = points
-        .iter()
-        .circular_tuple_windows()
-        .map(|(a, b)| {
-            let (x1, y1) = (a.0 as isize, a.1 as isize);
-            let (x2, y2) = (b.0 as isize, b.1 as isize);
-
-            //in priciple, one of these terms should be 0, since the
-            //edges are either horizontal or vertical.
-            x1.abs_diff(x2) + y1.abs_diff(y2)
-        })
-        .sum::<usize>();
-
-    // pick's theorem
-    interior_points + boundary_points.div(2) + 1
-}
-
-fn p1(input: &str) -> usize {
+fn p1(input: &str) -> u64 {
     let trenches = parse(input);
-    let directions: Vec<Direction> = trenches.iter().map(|trench| trench.direction).collect();
-    let points = get_points(&directions);
+    let points = vertices(trenches.iter().map(|t| (t.direction, t.distance)));
 
-    area(&points)
+    // The lagoon is both the trench and everything it encloses.
+    lattice_points(&points).expect("The trench doesn't enclose anything")
 }
 
-fn p2(input: &str) -> usize {
+fn p2(input: &str) -> u64 {
     let trenches = parse(input);
 
-    let directions: Vec<Direction> = trenches
-        .iter()
-        .map(|trench| {
-            let dist = usize::from_str_radix(&trench.color[..5], 16).unwrap();
-
-            let dir = match trench.color.chars().nth(5).unwrap() {
-                '0' => 'R',
-                '1' => 'D',
-                '2' => 'L',
-                '3' => 'U',
-                dir => panic!("Received invalid direction {dir}"),
-            };
+    let instructions = trenches.iter().map(|trench| {
+        let dist = i64::from_str_radix(&trench.color[..5], 16).unwrap();
 
-            Dir::from(dir, dist)
-        })
-        .collect();
+        let dir = match trench.color.chars().nth(5).unwrap() {
+            '0' => 'R',
+            '1' => 'D',
+            '2' => 'L',
+            '3' => 'U',
+            dir => panic!("Received invalid direction {dir}"),
+        };
 
-    let points = get_points(&directions);
+        (direction(dir), dist)
+    });
 
-    area(&points)
+    lattice_points(&vertices(instructions)).expect("The trench doesn't enclose anything")
 }
 
 fn main() {
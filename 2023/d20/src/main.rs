@@ -1,6 +1,6 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    env, fs,
+    env, fs, iter,
 };
 
 use Module::*;
@@ -165,96 +165,310 @@ impl Module {
     }
 }
 
-fn simulate(
-    mut graph: Graph,
-    n: usize,
-    interesting_modules: HashSet<String>,
-) -> (usize, HashMap<String, usize>) {
-    // interesting_modules are modules for which we want to find out the lowest number
-    // of button presses until they send a high signal.
-    let (mut acc_low, mut acc_high): (usize, usize) = (0, 0);
+impl Module {
+    fn outputs(&self) -> &[String] {
+        match self {
+            Broadcast { outputs, .. } | FlipFlop { outputs, .. } | Conjunction { outputs, .. } => {
+                outputs
+            }
+        }
+    }
+}
 
-    let mut im_cycle_map: HashMap<String, usize> = HashMap::new();
+fn to_dot(graph: &Graph) -> String {
+    // Render the network for Graphviz. Flip-flops are drawn as circles and
+    // conjunctions as boxes, so the counters are easy to spot.
+    let mut names: Vec<&String> = graph.keys().collect();
+    names.sort();
 
-    for i in 1..=n {
-        let mut message_queue = VecDeque::from([Message {
-            from: "button".to_string(),
-            to: "broadcaster".to_string(),
-            pulse: false,
-        }]);
+    let mut dot = String::from("digraph {\n");
 
-        let (mut n_low, mut n_high): (usize, usize) = (0, 0);
+    for name in names {
+        let module = &graph[name];
 
-        while let Some(message) = message_queue.pop_front() {
-            if let Some(interesting_module) = interesting_modules.get(&message.from[..]) {
-                if message.pulse {
-                    im_cycle_map
-                        .entry(interesting_module.to_string())
-                        .or_insert(i);
-                }
-            }
+        let shape = match module {
+            Broadcast { .. } => "doublecircle",
+            FlipFlop { .. } => "circle",
+            Conjunction { .. } => "box",
+        };
+
+        dot.push_str(&format!("    {name} [shape={shape}];\n"));
 
+        for output in module.outputs() {
+            dot.push_str(&format!("    {name} -> {output};\n"));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+fn press(graph: &mut Graph, mut observe: impl FnMut(&Message)) {
+    // Press the button once, calling `observe` on every pulse that is sent.
+    let mut message_queue = VecDeque::from([Message {
+        from: "button".to_string(),
+        to: "broadcaster".to_string(),
+        pulse: false,
+    }]);
+
+    while let Some(message) = message_queue.pop_front() {
+        observe(&message);
+
+        // Not all recipients might be in the graph.
+        // See second example (one with "output") from puzzle page.
+        if let Some(module) = graph.get_mut(&message.to) {
+            message_queue.extend(module.process(message));
+        }
+    }
+}
+
+fn simulate(mut graph: Graph, n: usize) -> usize {
+    let (mut acc_low, mut acc_high): (usize, usize) = (0, 0);
+
+    for _ in 1..=n {
+        press(&mut graph, |message| {
             if message.pulse {
-                n_high += 1;
+                acc_high += 1;
             } else {
-                n_low += 1;
+                acc_low += 1;
             }
+        });
+    }
 
-            // Not all recipients might be in the graph.
-            // See second example (one with "output") from puzzle page.
-            if let Some(module) = graph.get_mut(&message.to) {
-                message_queue.extend(module.process(message));
+    acc_low * acc_high
+}
+
+#[derive(Debug, PartialEq)]
+struct Counter {
+    // The flip-flops of the counter, from its least significant bit upwards.
+    bits: Vec<String>,
+    // The conjunction that fires (sends a low pulse) once all of the flip-flops
+    // it listens to are on, and resets the counter.
+    hub: String,
+    // The input of the module feeding "rx" that this counter drives.
+    output: String,
+    period: usize,
+}
+
+fn rx_input(graph: &Graph) -> Result<&String, String> {
+    let feeders: Vec<&String> = graph
+        .iter()
+        .filter(|(_, module)| module.outputs().contains(&"rx".to_string()))
+        .map(|(name, _)| name)
+        .collect();
+
+    match feeders[..] {
+        [name] => match graph[name] {
+            Conjunction { .. } => Ok(name),
+            _ => Err(format!("{name} feeds rx but is not a conjunction")),
+        },
+        [] => Err("No module feeds rx".to_string()),
+        _ => Err(format!("Multiple modules feed rx: {:?}", feeders)),
+    }
+}
+
+fn analyze_counter(graph: &Graph, start: &str) -> Result<(Vec<String>, String, usize), String> {
+    // Walk the flip-flop chain starting at `start`. Each flip-flop may pass
+    // its pulse on to the next flip-flop of the chain, and may report to the
+    // hub. The flip-flops reporting to the hub are the 1 bits of the period.
+    let mut bits = Vec::new();
+    let mut hub: Option<&String> = None;
+    let mut period = 0;
+    let mut current = Some(start.to_string());
+
+    while let Some(name) = current {
+        let Some(module @ FlipFlop { .. }) = graph.get(&name) else {
+            return Err(format!(
+                "{name} is part of a counter chain but isn't a flip-flop"
+            ));
+        };
+
+        let (flip_flops, conjunctions): (Vec<&String>, Vec<&String>) = module
+            .outputs()
+            .iter()
+            .partition(|o| matches!(graph.get(*o), Some(FlipFlop { .. })));
+
+        match (&conjunctions[..], hub) {
+            ([], _) => {}
+            ([c], None) => hub = Some(*c),
+            ([c], Some(h)) if *c == h => {}
+            _ => {
+                return Err(format!(
+                    "{name} reports to {:?}, expected a single hub",
+                    conjunctions
+                ))
             }
         }
 
-        acc_low += n_low;
-        acc_high += n_high;
+        if conjunctions.len() == 1 {
+            period |= 1 << bits.len();
+        }
+
+        bits.push(name.clone());
+
+        current = match flip_flops[..] {
+            [] => None,
+            [next] => Some(next.clone()),
+            _ => return Err(format!("{name} branches into flip-flops {:?}", flip_flops)),
+        };
     }
 
-    (acc_low * acc_high, im_cycle_map)
+    let Some(hub) = hub else {
+        return Err(format!("The chain starting at {start} has no hub"));
+    };
+
+    // The hub only fires at the period if it listens to exactly the 1 bits.
+    let Conjunction { memory, .. } = &graph[hub] else {
+        return Err(format!("{hub} is not a conjunction"));
+    };
+
+    let listens_to: HashSet<&String> = memory.keys().collect();
+    let one_bits: HashSet<&String> = bits
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| period >> i & 1 == 1)
+        .map(|(_, name)| name)
+        .collect();
+
+    if listens_to != one_bits {
+        return Err(format!(
+            "{hub} listens to {:?}, but the chain reports {:?}",
+            listens_to, one_bits
+        ));
+    }
+
+    Ok((bits, hub.clone(), period))
+}
+
+fn analyze(graph: &Graph) -> Result<Vec<Counter>, String> {
+    // Decompose the network into independent counters, one for each output of
+    // the broadcaster, and work out which input of the module feeding "rx"
+    // each counter drives. A counter either drives it directly through its hub
+    // or through an inverter (a conjunction with a single input).
+    let rx_input = rx_input(graph)?;
+
+    let Conjunction { memory, .. } = &graph[rx_input] else {
+        unreachable!("{rx_input} is a conjuction module!");
+    };
+
+    let Some(Broadcast { outputs, .. }) = graph.get("broadcaster") else {
+        return Err("No broadcaster found".to_string());
+    };
+
+    let mut counters = Vec::new();
+
+    for start in outputs {
+        let (bits, hub, period) = analyze_counter(graph, start)?;
+
+        let is_inverter = |name: &String| {
+            matches!(
+                graph.get(name),
+                Some(Conjunction { memory, .. }) if memory.len() == 1 && memory.contains_key(&hub)
+            )
+        };
+
+        let output = iter::once(&hub)
+            .chain(graph[&hub].outputs())
+            .filter(|name| memory.contains_key(*name))
+            .find(|name| **name == hub || is_inverter(name))
+            .ok_or(format!(
+                "The counter with hub {hub} doesn't drive {rx_input}"
+            ))?;
+
+        counters.push(Counter {
+            bits,
+            output: output.clone(),
+            hub,
+            period,
+        });
+    }
+
+    let driven: HashSet<&String> = counters.iter().map(|c| &c.output).collect();
+
+    if driven.len() != counters.len() || driven.len() != memory.len() {
+        return Err(format!(
+            "{rx_input} listens to {:?}, but the counters drive {:?}",
+            memory.keys().collect::<Vec<_>>(),
+            driven
+        ));
+    }
+
+    Ok(counters)
+}
+
+fn verify(mut graph: Graph, counters: &[Counter]) -> Result<(), String> {
+    // Simulate the network for two full periods of every counter, checking
+    // that each counter's output fires exactly at multiples of its period,
+    // i.e., that the cycle starts cleanly at the first press.
+    let n = counters.iter().map(|c| c.period).max().unwrap_or(0) * 2;
+
+    let mut firings: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for i in 1..=n {
+        press(&mut graph, |message| {
+            if message.pulse {
+                firings.entry(message.from.clone()).or_default().push(i);
+            }
+        });
+    }
+
+    for counter in counters {
+        let expected: Vec<usize> = (1..=n / counter.period)
+            .map(|k| k * counter.period)
+            .collect();
+
+        let mut observed = firings.remove(&counter.output).unwrap_or_default();
+        observed.dedup();
+
+        if observed != expected {
+            return Err(format!(
+                "{} fired at presses {:?}, expected {:?}",
+                counter.output, observed, expected
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn first_low_to_rx(mut graph: Graph, n: usize) -> Option<usize> {
+    (1..=n).find(|_| {
+        let mut low = false;
+
+        press(&mut graph, |message| {
+            low |= message.to == "rx" && !message.pulse;
+        });
+
+        low
+    })
 }
 
 fn p1(input: &str) -> usize {
-    let (ans, _) = simulate(parse(input), 1000, HashSet::new());
-    ans
+    simulate(parse(input), 1000)
 }
 
 fn p2(input: &str) -> usize {
-    // NOTE: Maybe not a super general solution but the idea is as follows
-    // Only a single (conjunction) module is connected to the input of "rx",
-    // which we will call `rx_input`.
-    // We consider the inputs to `rx_input`, as `interesting_modules`; Because
-    // when all of these modules send high pulses at the same time, `rx_input`
-    // will send a low pulse to "rx".
-    // From examining the simulation, those modules send a high pulse once
-    // every couple thousand button presses, in a cycle.
-    // In our simulation, we try to find the first time, i.e., at which button press,
-    // each of those modules send a high pulse.
-    // We then take the LCM of those numbers, which is the answer.
+    // Only a single (conjunction) module is connected to the input of "rx".
+    // It sends a low pulse once all of its inputs are high, and each of them
+    // is driven by an independent counter: a chain of flip-flops whose hub
+    // fires and resets the counter once its period is reached. The period can
+    // be read off the flip-flops that report to the hub.
+    // So rx receives a low pulse at the LCM of the periods.
+    // If the network isn't made of counters, we fall back to pressing the
+    // button a limited number of times.
     let graph = parse(input);
 
-    let rx_input = graph
-        .iter()
-        .find_map(|(name, module)| match module {
-            // In my input atleast, a Conjunction module with a single output
-            // of "rx" was present. I assume that is the case for all inputs.
-            Conjunction { outputs, .. } => {
-                if outputs.contains(&"rx".to_string()) {
-                    Some(name)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        })
-        .unwrap();
-
-    let interesting_modules: HashSet<String> = match graph.get(rx_input).unwrap() {
-        Conjunction { memory, .. } => memory.keys().cloned().collect(),
-        _ => unreachable!("{rx_input} is a conjuction module!"),
+    let counters = match analyze(&graph) {
+        Ok(counters) => counters,
+        Err(reason) => {
+            return first_low_to_rx(graph, 10000)
+                .unwrap_or_else(|| panic!("rx got no low pulse in 10000 presses, and {reason}"))
+        }
     };
 
-    let (_, im_cycles) = simulate(graph, 10000, interesting_modules);
+    if let Err(reason) = verify(parse(input), &counters) {
+        panic!("The counters don't cycle cleanly: {reason}");
+    }
 
     fn gcd(a: usize, b: usize) -> usize {
         if b == 0 {
@@ -268,7 +482,7 @@ fn p2(input: &str) -> usize {
         a / gcd(a, b) * b
     }
 
-    im_cycles.into_values().reduce(lcm).unwrap()
+    counters.iter().map(|c| c.period).reduce(lcm).unwrap()
 }
 
 fn main() {
@@ -282,6 +496,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "dot" => print!("{}", to_dot(&parse(&input))),
         _ => panic!("Invalid part"),
     };
 }
@@ -303,4 +518,68 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE2), 1);
     }
+
+    // Two counters with periods 5 (0b101) and 3 (0b11), each driving "rx"'s
+    // feeder through an inverter.
+    const COUNTERS: &str = "broadcaster -> a0, b0
+%a0 -> ha, a1
+%a1 -> a2
+%a2 -> ha
+&ha -> a0, a1, ia
+&ia -> f
+%b0 -> hb, b1
+%b1 -> hb
+&hb -> b0, ib
+&ib -> f
+&f -> rx";
+
+    #[test]
+    fn test_analyze() {
+        let graph = parse(COUNTERS);
+        let mut counters = analyze(&graph).unwrap();
+        counters.sort_by_key(|c| c.period);
+
+        assert_eq!(
+            counters,
+            vec![
+                Counter {
+                    bits: vec!["b0".to_string(), "b1".to_string()],
+                    hub: "hb".to_string(),
+                    output: "ib".to_string(),
+                    period: 3,
+                },
+                Counter {
+                    bits: vec!["a0".to_string(), "a1".to_string(), "a2".to_string()],
+                    hub: "ha".to_string(),
+                    output: "ia".to_string(),
+                    period: 5,
+                },
+            ]
+        );
+
+        assert_eq!(verify(graph, &counters), Ok(()));
+        assert_eq!(p2(COUNTERS), 15);
+        assert_eq!(first_low_to_rx(parse(COUNTERS), 100), Some(15));
+    }
+
+    #[test]
+    fn test_analyze_errors() {
+        assert_eq!(
+            analyze(&parse(EXAMPLE1)),
+            Err("No module feeds rx".to_string())
+        );
+
+        // The hub doesn't listen to a2, so the counter resets at the wrong time.
+        let broken = COUNTERS.replace("%a2 -> ha", "%a2 -> ia");
+        assert!(analyze(&parse(&broken)).is_err());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = to_dot(&parse(EXAMPLE2));
+
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains("    con [shape=box];\n"));
+        assert!(dot.contains("    con -> rx;\n"));
+    }
 }
@@ -1,73 +1,118 @@
-use std::{collections::HashMap, env, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+};
+
+// Each rule `x|y` is an edge x -> y, meaning x has to be printed before y.
+// The rules as a whole may well contain cycles; only the rules between pages
+// of the same update have to be consistent.
+struct Rules {
+    after: HashMap<i32, HashSet<i32>>,
+}
+
+impl Rules {
+    fn before(&self, x: i32, y: i32) -> bool {
+        self.after.get(&x).is_some_and(|pages| pages.contains(&y))
+    }
+
+    fn violation(&self, update: &[i32]) -> Option<(i32, i32)> {
+        // The first rule `x|y` broken by `y` being printed before `x`.
+        update.iter().enumerate().find_map(|(i, &y)| {
+            update[i + 1..]
+                .iter()
+                .find(|&&x| self.before(x, y))
+                .map(|&x| (x, y))
+        })
+    }
+
+    fn order(&self, update: &[i32]) -> Result<Vec<i32>, Vec<i32>> {
+        // Kahn's algorithm on the rules between the pages of `update`, always
+        // picking the ready page that comes first in `update`. If the rules
+        // form a cycle, the pages on it are returned instead.
+        let pages: HashSet<i32> = update.iter().copied().collect();
+
+        let mut n_before: HashMap<i32, usize> = update.iter().map(|&page| (page, 0)).collect();
+
+        for &x in update {
+            for &y in self.after.get(&x).into_iter().flatten() {
+                if pages.contains(&y) {
+                    *n_before.get_mut(&y).unwrap() += 1;
+                }
+            }
+        }
+
+        let mut ordered = Vec::with_capacity(update.len());
 
-fn parse(input: &str) -> (HashMap<i32, Vec<i32>>, Vec<Vec<i32>>) {
-    // Key is the page number and the values are the pages that are supposed
-    // to be after the key.
-    let mut orderings: HashMap<i32, Vec<i32>> = HashMap::new();
+        while let Some(&page) = update.iter().find(|page| n_before.get(page) == Some(&0)) {
+            n_before.remove(&page);
+            ordered.push(page);
+
+            for y in self.after.get(&page).into_iter().flatten() {
+                if let Some(n) = n_before.get_mut(y) {
+                    *n -= 1;
+                }
+            }
+        }
+
+        if n_before.is_empty() {
+            return Ok(ordered);
+        }
+
+        // What's left is on a cycle or after one. Peeling off the pages that
+        // have nothing left after them leaves just the cycles.
+        let mut cycle: HashSet<i32> = n_before.into_keys().collect();
+
+        while let Some(&x) = cycle
+            .iter()
+            .find(|&&x| !cycle.iter().any(|&y| self.before(x, y)))
+        {
+            cycle.remove(&x);
+        }
+
+        let mut cycle: Vec<i32> = cycle.into_iter().collect();
+        cycle.sort();
+        Err(cycle)
+    }
+}
+
+fn parse(input: &str) -> (Rules, Vec<Vec<i32>>) {
+    let mut after: HashMap<i32, HashSet<i32>> = HashMap::new();
     let mut updates: Vec<Vec<i32>> = Vec::new();
 
     for line in input.lines() {
         if line.contains('|') {
             let parts: Vec<i32> = line.split('|').map(|x| x.parse().unwrap()).collect();
-            orderings.entry(parts[0]).or_default().push(parts[1]);
+            after.entry(parts[0]).or_default().insert(parts[1]);
         } else if line.contains(',') {
             let nums: Vec<i32> = line.split(',').map(|x| x.parse().unwrap()).collect();
             updates.push(nums);
         }
     }
 
-    (orderings, updates)
-}
-
-fn check(update: &[i32], orderings: &HashMap<i32, Vec<i32>>) -> bool {
-    let update_len = update.len();
-
-    for (i, &current_page) in update.iter().enumerate().take(update_len - 1) {
-        for &next_page in update.iter().skip(i + 1) {
-            if let Some(pages) = orderings.get(&next_page) {
-                if pages.contains(&current_page) {
-                    return false;
-                }
-            }
-        }
-    }
-    true
+    (Rules { after }, updates)
 }
 
 fn p1(input: &str) -> i32 {
-    let (orderings, updates) = parse(input);
+    let (rules, updates) = parse(input);
 
     updates
         .iter()
-        .filter(|&v| check(v, &orderings))
+        .filter(|v| rules.violation(v).is_none())
         .map(|v| v[v.len() / 2])
         .sum()
 }
 
-fn fix(mut update: Vec<i32>, orderings: &HashMap<i32, Vec<i32>>) -> Vec<i32> {
-    let update_len = update.len();
-
-    for (i, &current_page) in update.iter().enumerate().take(update_len - 1) {
-        for (j, &next_page) in update.iter().enumerate().skip(i + 1) {
-            if let Some(pages) = orderings.get(&next_page) {
-                if pages.contains(&current_page) {
-                    update.swap(i, j);
-                    return fix(update, orderings);
-                }
-            }
-        }
-    }
-
-    update
-}
-
 fn p2(input: &str) -> i32 {
-    let (orderings, updates) = parse(input);
+    let (rules, updates) = parse(input);
 
     updates
         .iter()
-        .filter(|v| !check(v, &orderings))
-        .map(|v| fix(v.clone(), &orderings))
+        .filter(|v| rules.violation(v).is_some())
+        .map(|v| {
+            rules.order(v).unwrap_or_else(|cycle| {
+                panic!("The rules for {:?} form a cycle through {:?}", v, cycle)
+            })
+        })
         .map(|v| v[v.len() / 2])
         .sum()
 }
@@ -102,4 +147,26 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 123);
     }
+
+    #[test]
+    fn test_violation() {
+        let (rules, updates) = parse(EXAMPLE);
+
+        assert_eq!(rules.violation(&updates[0]), None);
+        // 97,75 is out of order.
+        assert_eq!(rules.violation(&updates[3]), Some((97, 75)));
+        assert_eq!(rules.order(&updates[3]), Ok(vec![97, 75, 47, 61, 53]));
+    }
+
+    #[test]
+    fn test_cycles() {
+        // 1 -> 2 -> 3 -> 1 is a cycle, but every update with only two of those
+        // pages can still be ordered.
+        let (rules, updates) = parse("1|2\n2|3\n3|1\n3|4\n\n2,1\n3,2,4\n1,4,3\n4,3,2,1");
+
+        assert_eq!(rules.order(&updates[0]), Ok(vec![1, 2]));
+        assert_eq!(rules.order(&updates[1]), Ok(vec![2, 3, 4]));
+        assert_eq!(rules.order(&updates[2]), Ok(vec![3, 1, 4]));
+        assert_eq!(rules.order(&updates[3]), Err(vec![1, 2, 3]));
+    }
 }
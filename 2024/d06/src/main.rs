@@ -1,124 +1,262 @@
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
     env, fs,
 };
 
-fn parse(input: &str) -> Vec<Vec<char>> {
-    input
-        .lines()
-        .map(|line| line.trim().chars().collect())
-        .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Dir {
+    Up,
+    Right,
+    Down,
+    Left,
 }
 
-fn get_pos_and_dir(grid: &[Vec<char>]) -> ((usize, usize), char) {
-    for (i, row) in grid.iter().enumerate() {
-        for (j, &cell) in row.iter().enumerate() {
-            if matches!(cell, '<' | 'v' | '^' | '>') {
-                return ((i, j), cell);
-            }
+use Dir::*;
+
+impl Dir {
+    fn from(c: char) -> Option<Self> {
+        match c {
+            '^' => Some(Up),
+            '>' => Some(Right),
+            'v' => Some(Down),
+            '<' => Some(Left),
+            _ => None,
         }
     }
 
-    panic!("No starting position found");
+    fn turn(self) -> Self {
+        match self {
+            Up => Right,
+            Right => Down,
+            Down => Left,
+            Left => Up,
+        }
+    }
 }
 
-fn step(grid: &[Vec<char>], pos: (usize, usize), dir: char) -> ((usize, usize), char) {
-    let (x, y) = pos;
+struct Lab {
+    width: usize,
+    height: usize,
+    obstacles: Vec<bool>,
+    start: usize,
+    dir: Dir,
+    // next[dir][cell] is where the guard stops, facing an obstacle, when
+    // walking from cell towards dir, or None if they walk off the map.
+    next: [Vec<Option<usize>>; 4],
+}
+
+impl Lab {
+    fn from(input: &str) -> Self {
+        let rows: Vec<&str> = input.lines().map(|line| line.trim()).collect();
+        let height = rows.len();
+        let width = rows[0].len();
+
+        let cells: Vec<char> = rows.iter().flat_map(|row| row.chars()).collect();
+        let obstacles: Vec<bool> = cells.iter().map(|&c| c == '#').collect();
 
-    match dir {
-        '<' => {
-            if grid[x][y - 1] != '#' {
-                ((x, y - 1), dir)
-            } else {
-                step(grid, pos, '^')
+        let (start, dir) = cells
+            .iter()
+            .enumerate()
+            .find_map(|(i, &c)| Some((i, Dir::from(c)?)))
+            .expect("No starting position found");
+
+        let mut lab = Lab {
+            width,
+            height,
+            obstacles,
+            start,
+            dir,
+            next: Default::default(),
+        };
+
+        for dir in [Up, Right, Down, Left] {
+            let mut next = vec![None; width * height];
+
+            // Going up or left the neighbour has a smaller index, so walk the
+            // cells forwards; going down or right, walk them backwards.
+            let order: Vec<usize> = match dir {
+                Up | Left => (0..width * height).collect(),
+                Down | Right => (0..width * height).rev().collect(),
+            };
+
+            for cell in order {
+                next[cell] = match lab.neighbour(cell, dir) {
+                    None => None,
+                    Some(ahead) if lab.obstacles[ahead] => Some(cell),
+                    Some(ahead) => next[ahead],
+                };
             }
+
+            lab.next[dir as usize] = next;
         }
-        'v' => {
-            if grid[x + 1][y] != '#' {
-                ((x + 1, y), dir)
-            } else {
-                step(grid, pos, '<')
-            }
+
+        lab
+    }
+
+    fn neighbour(&self, cell: usize, dir: Dir) -> Option<usize> {
+        let (row, col) = (cell / self.width, cell % self.width);
+
+        match dir {
+            Up if row > 0 => Some(cell - self.width),
+            Right if col + 1 < self.width => Some(cell + 1),
+            Down if row + 1 < self.height => Some(cell + self.width),
+            Left if col > 0 => Some(cell - 1),
+            _ => None,
+        }
+    }
+
+    // How many steps towards dir it takes to get from cell to target, if
+    // target is ahead of cell at all.
+    fn distance(&self, cell: usize, dir: Dir, target: usize) -> Option<usize> {
+        let (row, col) = (cell / self.width, cell % self.width);
+        let (t_row, t_col) = (target / self.width, target % self.width);
+
+        match dir {
+            Up if col == t_col && t_row <= row => Some(row - t_row),
+            Right if row == t_row && t_col >= col => Some(t_col - col),
+            Down if col == t_col && t_row >= row => Some(t_row - row),
+            Left if row == t_row && t_col <= col => Some(col - t_col),
+            _ => None,
         }
-        '^' => {
-            if grid[x - 1][y] != '#' {
-                ((x - 1, y), dir)
-            } else {
-                step(grid, pos, '>')
+    }
+
+    fn jump(&self, cell: usize, dir: Dir, obstruction: Option<usize>) -> Option<usize> {
+        let stop = self.next[dir as usize][cell];
+
+        // The extra obstruction only matters if the guard would reach it
+        // before the obstacle the table knows about.
+        let blocked = obstruction.and_then(|o| {
+            let to_obstruction = self.distance(cell, dir, o).filter(|&d| d > 0)?;
+            let to_stop = stop.map(|stop| self.distance(cell, dir, stop).unwrap());
+
+            match to_stop {
+                Some(to_stop) if to_obstruction > to_stop + 1 => None,
+                _ => Some(to_obstruction),
             }
+        });
+
+        match blocked {
+            Some(d) => (1..d).try_fold(cell, |c, _| self.neighbour(c, dir)),
+            None => stop,
         }
-        '>' => {
-            if grid[x][y + 1] != '#' {
-                ((x, y + 1), dir)
-            } else {
-                step(grid, pos, 'v')
+    }
+
+    // Follows the guard from turn to turn. If they end up in a loop, this
+    // returns the cells they turn at along the loop, in order.
+    fn patrol(&self, cell: usize, dir: Dir, obstruction: Option<usize>) -> Option<Vec<usize>> {
+        let mut turns = Vec::new();
+        let mut seen = HashMap::new();
+        let (mut cell, mut dir) = (cell, dir);
+
+        loop {
+            cell = self.jump(cell, dir, obstruction)?;
+
+            if let Some(&i) = seen.get(&(cell, dir)) {
+                return Some(turns[i..].to_vec());
             }
+
+            seen.insert((cell, dir), turns.len());
+            turns.push(cell);
+
+            dir = dir.turn();
         }
-        _ => panic!("Invalid direction"),
     }
-}
 
-fn travel(
-    grid: &[Vec<char>],
-    pos: (usize, usize),
-    dir: char,
-    mut record: HashMap<(usize, usize), HashSet<char>>,
-) -> (HashMap<(usize, usize), HashSet<char>>, bool) {
-    let (x, y) = pos;
-
-    if let Some(dirs) = record.get(&pos) {
-        if dirs.contains(&dir) {
-            return (record, true);
+    // Every state of the guard on the original route, step by step.
+    fn route(&self) -> Vec<(usize, Dir)> {
+        let mut route = Vec::new();
+        let (mut cell, mut dir) = (self.start, self.dir);
+
+        loop {
+            route.push((cell, dir));
+
+            match self.neighbour(cell, dir) {
+                None => return route,
+                Some(ahead) if self.obstacles[ahead] => dir = dir.turn(),
+                Some(ahead) => cell = ahead,
+            }
         }
     }
 
-    record.entry(pos).or_default().insert(dir);
+    // Each cell an obstruction could go in, along with the state the guard is
+    // in right before they would first run into it. Everything up to there
+    // is the same as on the original route.
+    fn candidates(&self) -> Vec<(usize, Dir, usize)> {
+        let mut seen = HashSet::from([self.start]);
+
+        self.route()
+            .windows(2)
+            .filter(|pair| seen.insert(pair[1].0))
+            .map(|pair| (pair[0].0, pair[0].1, pair[1].0))
+            .collect()
+    }
+
+    fn obstruction_loop(&self, obstruction: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        let obstruction = obstruction.0 * self.width + obstruction.1;
 
-    if (dir == '<' && y == 0)
-        || (dir == 'v' && x == grid.len() - 1)
-        || (dir == '^' && x == 0)
-        || (dir == '>' && y == grid[x].len() - 1)
-    {
-        return (record, false);
+        self.patrol(self.start, self.dir, Some(obstruction))
+            .map(|turns| {
+                turns
+                    .iter()
+                    .map(|cell| (cell / self.width, cell % self.width))
+                    .collect()
+            })
     }
 
-    let (new_pos, new_dir) = step(grid, pos, dir);
+    fn render_loop(&self, obstruction: (usize, usize)) -> Option<String> {
+        let turns: HashSet<_> = self.obstruction_loop(obstruction)?.into_iter().collect();
 
-    travel(grid, new_pos, new_dir, record)
+        let rows = (0..self.height).map(|row| {
+            (0..self.width)
+                .map(|col| match (row, col) {
+                    p if p == obstruction => 'O',
+                    p if turns.contains(&p) => '+',
+                    _ if self.obstacles[row * self.width + col] => '#',
+                    _ => '.',
+                })
+                .collect::<String>()
+        });
+
+        Some(rows.collect::<Vec<_>>().join("\n"))
+    }
 }
 
 fn p1(input: &str) -> usize {
-    let grid = parse(input);
-    let (pos, dir) = get_pos_and_dir(&grid);
-
-    let (record, _) = travel(&grid, pos, dir, HashMap::new());
+    let lab = Lab::from(input);
 
-    record.len()
+    lab.route()
+        .iter()
+        .map(|&(cell, _)| cell)
+        .collect::<HashSet<_>>()
+        .len()
 }
 
 fn p2(input: &str) -> usize {
-    let mut grid = parse(input);
-    let (starting_pos, dir) = get_pos_and_dir(&grid);
-
-    let (mut record, _) = travel(&grid, starting_pos, dir, HashMap::new());
-
-    // Remove start position
-    record.remove(&starting_pos);
-
-    // Insert a # at each visited position
-    // and check if there is a loop
-    record
-        .keys()
-        .filter(|&pos| {
-            let (x, y) = pos;
-            grid[*x][*y] = '#';
-            let (_, has_loop) = travel(&grid, starting_pos, dir, HashMap::new());
-            grid[*x][*y] = '.';
-            has_loop
-        })
+    let lab = Lab::from(input);
+    let candidates = lab.candidates();
+
+    #[cfg(feature = "parallel")]
+    let candidates = candidates.par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let candidates = candidates.iter();
+
+    candidates
+        .filter(|&&(cell, dir, obstruction)| lab.patrol(cell, dir, Some(obstruction)).is_some())
         .count()
 }
 
+fn loops(input: &str) -> String {
+    let lab = Lab::from(input);
+
+    lab.candidates()
+        .iter()
+        .filter_map(|&(_, _, o)| lab.render_loop((o / lab.width, o % lab.width)))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -130,6 +268,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "loops" => println!("{}", loops(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -149,4 +288,28 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 6);
     }
+
+    #[test]
+    fn test_obstruction_loop() {
+        let lab = Lab::from(EXAMPLE);
+
+        assert_eq!(
+            lab.obstruction_loop((6, 3)),
+            Some(vec![(1, 4), (1, 8), (6, 8), (6, 4)])
+        );
+        assert_eq!(lab.obstruction_loop((0, 0)), None);
+    }
+
+    #[test]
+    fn test_from_start() {
+        // Starting every candidate from the beginning gives the same loops.
+        let lab = Lab::from(EXAMPLE);
+
+        let loops = (0..lab.width * lab.height)
+            .filter(|&o| o != lab.start && !lab.obstacles[o])
+            .filter(|&o| lab.patrol(lab.start, lab.dir, Some(o)).is_some())
+            .count();
+
+        assert_eq!(loops, p2(EXAMPLE));
+    }
 }
@@ -1,83 +1,190 @@
-use std::{collections::HashMap, env, fs};
+use std::{env, fs};
 
-fn parse(input: &str) -> HashMap<u64, Vec<u64>> {
-    let mut equations: HashMap<u64, Vec<u64>> = HashMap::new();
+struct Equation {
+    target: u64,
+    numbers: Vec<u64>,
+}
 
-    for line in input.lines() {
-        if let Some((key_str, value_str)) = line.split_once(':') {
-            let key: u64 = key_str.trim().parse().unwrap();
-            let values: Vec<u64> = value_str
+fn parse(input: &str) -> Vec<Equation> {
+    // Several equations may share a target, so they are kept as a list.
+    input
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(target, numbers)| Equation {
+            target: target.trim().parse().unwrap(),
+            numbers: numbers
                 .split_whitespace()
-                .map(|v| v.trim().parse().unwrap())
-                .collect();
+                .map(|v| v.parse().unwrap())
+                .collect(),
+        })
+        .collect()
+}
 
-            equations.insert(key, values);
-        }
+// What `lhs` has to be for `lhs op rhs` to give some result.
+enum Lhs {
+    Exactly(u64),
+    Anything,
+}
+
+use Lhs::*;
+
+trait Operator {
+    fn symbol(&self) -> &str;
+
+    // `lhs op rhs`, or None if it isn't defined (e.g., it would overflow).
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64>;
+
+    // The `lhs` for which `lhs op rhs == result`, if there is one.
+    fn undo(&self, result: u64, rhs: u64) -> Option<Lhs>;
+}
+
+struct Add;
+struct Mul;
+struct Concat;
+
+impl Operator for Add {
+    fn symbol(&self) -> &str {
+        "+"
+    }
+
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_add(rhs)
     }
 
-    equations
+    fn undo(&self, result: u64, rhs: u64) -> Option<Lhs> {
+        result.checked_sub(rhs).map(Exactly)
+    }
 }
 
-fn calibrate(eq: &Vec<u64>, target: u64, allowed_operators: &str, op_acc: Vec<char>) -> bool {
-    if eq.len() - 1 != op_acc.len() {
-        for op in allowed_operators.chars() {
-            let mut new_operators = op_acc.clone();
-            new_operators.push(op);
-            if calibrate(eq, target, allowed_operators, new_operators) {
-                return true;
-            }
-        }
+impl Operator for Mul {
+    fn symbol(&self) -> &str {
+        "*"
+    }
+
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_mul(rhs)
+    }
 
-        false
-    } else {
-        let mut result: u64 = eq[0];
-        for i in 0..op_acc.len() {
-            if result > target {
-                return false;
-            }
-            match op_acc[i] {
-                '*' => result *= eq[i + 1],
-                '+' => result += eq[i + 1],
-                '|' => {
-                    let mut num = eq[i + 1];
-
-                    while num > 0 {
-                        result *= 10;
-                        num /= 10;
-                    }
-
-                    result += eq[i + 1];
-                }
-                _ => panic!("Invalid operator"),
-            }
+    fn undo(&self, result: u64, rhs: u64) -> Option<Lhs> {
+        match rhs {
+            // Anything times 0 is 0.
+            0 => (result == 0).then_some(Anything),
+            _ => result.is_multiple_of(rhs).then(|| Exactly(result / rhs)),
         }
+    }
+}
 
-        result == target
+fn shift(rhs: u64) -> u64 {
+    // The power of 10 that makes room for the digits of `rhs`.
+    10u64.pow(rhs.checked_ilog10().unwrap_or(0) + 1)
+}
+
+impl Operator for Concat {
+    fn symbol(&self) -> &str {
+        "||"
+    }
+
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_mul(shift(rhs))?.checked_add(rhs)
+    }
+
+    fn undo(&self, result: u64, rhs: u64) -> Option<Lhs> {
+        // `result` has to end in the digits of `rhs`.
+        let shift = shift(rhs);
+        (result % shift == rhs).then(|| Exactly(result / shift))
     }
 }
 
-fn p1(input: &str) -> u64 {
-    let equations = parse(input);
-    let allowed_operators = "*+";
+fn solve<'a>(
+    numbers: &[u64],
+    target: u64,
+    operators: &[&'a dyn Operator],
+) -> Option<Vec<&'a dyn Operator>> {
+    // Operators are evaluated left to right, so the last operator is the one
+    // applied last. Working backwards from the target, every operator has to be
+    // able to produce the target from the last number, which prunes most of
+    // the options right away (e.g., multiplication needs the target to be
+    // divisible by the last number).
+    let (&last, rest) = numbers.split_last()?;
 
-    equations
-        .iter()
-        .filter(|(target, eq)| calibrate(eq, **target, allowed_operators, Vec::new()))
-        .map(|(target, _)| target)
-        .sum()
+    if rest.is_empty() {
+        return (last == target).then(Vec::new);
+    }
+
+    operators.iter().find_map(|&op| {
+        let mut solution = match op.undo(target, last)? {
+            Exactly(lhs) => solve(rest, lhs, operators)?,
+            Anything => evaluate(rest, operators)?,
+        };
+        solution.push(op);
+
+        Some(solution)
+    })
 }
 
-fn p2(input: &str) -> u64 {
-    let equations = parse(input);
-    let allowed_operators = "*+|";
+fn evaluate<'a>(numbers: &[u64], operators: &[&'a dyn Operator]) -> Option<Vec<&'a dyn Operator>> {
+    // Any way of combining `numbers` at all, for when the result doesn't
+    // matter. Only overflowing rules an operator out.
+    fn go<'a>(
+        acc: u64,
+        numbers: &[u64],
+        operators: &[&'a dyn Operator],
+    ) -> Option<Vec<&'a dyn Operator>> {
+        let Some((&next, rest)) = numbers.split_first() else {
+            return Some(Vec::new());
+        };
+
+        operators.iter().find_map(|&op| {
+            let mut solution = go(op.apply(acc, next)?, rest, operators)?;
+            solution.insert(0, op);
+            Some(solution)
+        })
+    }
 
-    equations
+    let (&first, rest) = numbers.split_first()?;
+    go(first, rest, operators)
+}
+
+fn expression(numbers: &[u64], operators: &[&dyn Operator]) -> String {
+    numbers[1..]
         .iter()
-        .filter(|(target, eq)| calibrate(eq, **target, allowed_operators, Vec::new()))
-        .map(|(target, _)| target)
+        .zip(operators)
+        .fold(numbers[0].to_string(), |acc, (n, op)| {
+            format!("{acc} {} {n}", op.symbol())
+        })
+}
+
+fn calibrate(input: &str, operators: &[&dyn Operator]) -> u64 {
+    parse(input)
+        .iter()
+        .filter(|eq| solve(&eq.numbers, eq.target, operators).is_some())
+        .map(|eq| eq.target)
         .sum()
 }
 
+fn explain(input: &str, operators: &[&dyn Operator]) -> String {
+    parse(input)
+        .iter()
+        .filter_map(|eq| {
+            let solution = solve(&eq.numbers, eq.target, operators)?;
+            Some(format!(
+                "{}: {}",
+                eq.target,
+                expression(&eq.numbers, &solution)
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn p1(input: &str) -> u64 {
+    calibrate(input, &[&Add, &Mul])
+}
+
+fn p2(input: &str) -> u64 {
+    calibrate(input, &[&Add, &Mul, &Concat])
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
@@ -89,6 +196,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "explain" => println!("{}", explain(&input, &[&Add, &Mul, &Concat])),
         _ => panic!("Invalid part"),
     };
 }
@@ -108,4 +216,73 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 11387);
     }
+
+    #[test]
+    fn test_expression() {
+        let operators: [&dyn Operator; 3] = [&Add, &Mul, &Concat];
+
+        let numbers = [6, 8, 6, 15];
+        let solution = solve(&numbers, 7290, &operators).unwrap();
+        assert_eq!(expression(&numbers, &solution), "6 * 8 || 6 * 15");
+
+        // Evaluating the expression left to right gives back the target.
+        let result = numbers[1..]
+            .iter()
+            .zip(&solution)
+            .try_fold(numbers[0], |acc, (&n, op)| op.apply(acc, n));
+        assert_eq!(result, Some(7290));
+
+        assert!(solve(&[17, 5], 83, &operators).is_none());
+    }
+
+    // Not part of the puzzle, just to show other operators fit in.
+    struct Sub;
+
+    impl Operator for Sub {
+        fn symbol(&self) -> &str {
+            "-"
+        }
+
+        fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+            lhs.checked_sub(rhs)
+        }
+
+        fn undo(&self, result: u64, rhs: u64) -> Option<Lhs> {
+            result.checked_add(rhs).map(Exactly)
+        }
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let operators: [&dyn Operator; 3] = [&Add, &Mul, &Sub];
+
+        let numbers = [10, 3, 4];
+        let solution = solve(&numbers, 28, &operators).unwrap();
+        assert_eq!(expression(&numbers, &solution), "10 - 3 * 4");
+    }
+
+    #[test]
+    fn test_zero() {
+        // x * 0 is 0 whatever x is.
+        assert_eq!(p1("0: 5 0\n7: 3 4 0 7\n1: 2 0"), 7);
+
+        let operators: [&dyn Operator; 3] = [&Add, &Mul, &Concat];
+        assert!(solve(&[5, 0], 0, &operators).is_some());
+
+        let numbers = [3, 4, 0, 7];
+        let solution = solve(&numbers, 7, &operators).unwrap();
+        assert_eq!(expression(&numbers, &solution), "3 + 4 * 0 + 7");
+
+        // With every operator overflowing, there's nothing to multiply by 0.
+        let numbers = [u64::MAX, 9, 0];
+        assert!(solve(&numbers, 0, &[&Add, &Mul, &Concat]).is_none());
+    }
+
+    #[test]
+    fn test_duplicate_targets() {
+        // Both equations count, even though they share a target.
+        let input = "10: 5 5\n10: 2 5\n10: 3 3";
+
+        assert_eq!(p1(input), 20);
+    }
 }
@@ -1,148 +1,194 @@
-use std::{env, fs};
+use std::{cmp::Reverse, collections::BinaryHeap, env, fs};
 
 fn readfile(filepath: &str) -> String {
     fs::read_to_string(filepath).unwrap()
 }
 
-fn parse(input: &str) -> String {
-    input.trim().to_string()
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    start: usize,
+    len: usize,
 }
 
-fn parse_diskmap(diskmap: &str) -> Vec<Option<usize>> {
-    let mut is_freespace: bool = false;
-    let mut diskmap_vec = Vec::new();
-    let mut curr_idx = 0;
-
-    for c in diskmap.chars() {
-        let size: u32 = c.to_digit(10).unwrap();
-
-        for _ in 0..size {
-            diskmap_vec.push(if is_freespace { None } else { Some(curr_idx) });
-        }
-
-        if !is_freespace {
-            curr_idx += 1;
-        }
-
-        is_freespace = !is_freespace;
+impl Span {
+    fn checksum(&self, id: usize) -> usize {
+        id * (self.start * self.len + self.len * self.len.saturating_sub(1) / 2)
     }
-
-    diskmap_vec
 }
 
-fn defragment_blocks(expanded_diskmap: Vec<Option<usize>>) -> Vec<usize> {
-    let mut right_idx = expanded_diskmap.len() - 1;
-
-    let mut defragmented_diskmap = Vec::new();
+#[derive(Clone)]
+struct Disk {
+    // The fragments of each file, indexed by file id.
+    files: Vec<Vec<Span>>,
+    // The free spans in the original disk map, from left to right.
+    free: Vec<Span>,
+    len: usize,
+}
 
-    let mut i = 0;
-    while i <= right_idx {
-        match expanded_diskmap[i] {
-            Some(a) => defragmented_diskmap.push(a),
-            None => {
-                while expanded_diskmap[right_idx].is_none() {
-                    right_idx -= 1;
+impl Disk {
+    fn from(diskmap: &str) -> Self {
+        let mut files = Vec::new();
+        let mut free: Vec<Span> = Vec::new();
+        let mut start = 0;
+
+        for (i, c) in diskmap.trim().chars().enumerate() {
+            let len = c.to_digit(10).unwrap() as usize;
+            let span = Span { start, len };
+
+            if i % 2 == 0 {
+                files.push(vec![span]);
+            } else if len > 0 {
+                // Free spans either side of an empty file are a single gap.
+                match free.last_mut() {
+                    Some(gap) if gap.start + gap.len == start => gap.len += len,
+                    _ => free.push(span),
                 }
-                defragmented_diskmap.push(expanded_diskmap[right_idx].unwrap());
-                right_idx -= 1;
             }
+
+            start += len;
+        }
+
+        Disk {
+            files,
+            free,
+            len: start,
         }
-        i += 1;
     }
 
-    defragmented_diskmap
-}
+    fn compact_blocks(&mut self) {
+        // Moves the last block into the first free block until there are no
+        // gaps left, a whole run of blocks at a time.
+        let mut free = self.free.iter().copied().peekable();
 
-fn p1(input: &str) -> usize {
-    let diskmap = parse(input);
-    let expanded_diskmap = parse_diskmap(&diskmap);
-    let defragmented_diskmap = defragment_blocks(expanded_diskmap);
-
-    defragmented_diskmap
-        .iter()
-        .enumerate()
-        .map(|(i, a)| i * a)
-        .sum()
-}
-
-fn defragment_files(mut expanded_diskmap: Vec<Option<usize>>) -> Vec<Option<usize>> {
-    let total_length = expanded_diskmap.len();
-    let mut right_idx = total_length - 1;
+        for fragments in self.files.iter_mut().rev() {
+            let file = fragments[0];
+            let mut remaining = file.len;
+            let mut moved = Vec::new();
 
-    while right_idx > 0 {
-        // move to start of non empty space from right
-        while expanded_diskmap[right_idx].is_none() {
-            right_idx -= 1;
-        }
+            while remaining > 0 {
+                let Some(gap) = free.peek_mut().filter(|gap| gap.start < file.start) else {
+                    break;
+                };
+
+                let len = remaining.min(gap.len);
+                moved.push(Span {
+                    start: gap.start,
+                    len,
+                });
+
+                gap.start += len;
+                gap.len -= len;
+                if gap.len == 0 {
+                    free.next();
+                }
 
-        let file_id = expanded_diskmap[right_idx].unwrap();
-        let mut file_size = 0;
+                remaining -= len;
+            }
 
-        while right_idx > file_size
-            && match expanded_diskmap[right_idx - file_size] {
-                Some(a) => a == file_id,
-                None => false,
+            fragments.clear();
+            if remaining > 0 {
+                fragments.push(Span {
+                    start: file.start,
+                    len: remaining,
+                });
             }
-        {
-            file_size += 1;
+            fragments.extend(moved);
         }
+    }
 
-        let mut left_idx = 0;
-        let mut empty_size = 0;
+    fn compact_files(&mut self) {
+        // gaps[n] holds the starts of the free spans of length n, so the
+        // leftmost gap a file fits in is the smallest top of gaps[len..].
+        let longest = self.free.iter().map(|gap| gap.len).max().unwrap_or(0);
+        let mut gaps = vec![BinaryHeap::new(); longest + 1];
+
+        for gap in &self.free {
+            gaps[gap.len].push(Reverse(gap.start));
+        }
 
-        while left_idx < right_idx - file_size {
-            // move to the start of an empty space from left
-            while expanded_diskmap[left_idx].is_some() {
-                left_idx += 1;
+        for fragments in self.files.iter_mut().rev() {
+            let file = fragments[0];
+            if file.len == 0 {
+                continue;
             }
 
-            // check from the left for the first empty space
-            // which is >= file_size
-            while expanded_diskmap[left_idx + empty_size].is_none() {
-                empty_size += 1;
-                if left_idx + empty_size >= total_length {
-                    break;
+            let best = (file.len..gaps.len())
+                .filter_map(|len| gaps[len].peek().map(|&Reverse(start)| (start, len)))
+                .filter(|&(start, _)| start < file.start)
+                .min();
+
+            // The space the file leaves behind is to the right of every file
+            // still to be moved, so it never needs to be indexed.
+            if let Some((start, len)) = best {
+                gaps[len].pop();
+                if len > file.len {
+                    gaps[len - file.len].push(Reverse(start + file.len));
                 }
-            }
 
-            if empty_size >= file_size {
-                break;
-            } else {
-                left_idx += empty_size;
-                empty_size = 0;
+                fragments[0] = Span {
+                    start,
+                    len: file.len,
+                };
             }
         }
+    }
 
-        // If found, move the file to the empty space
-        if empty_size >= file_size && left_idx < right_idx {
-            // everything from right_idx to right_idx - file_size should move
-            // to left_idx to left_idx + file_size
+    fn blocks(&self) -> Vec<Option<usize>> {
+        let mut blocks = vec![None; self.len];
 
-            for i in 0..file_size {
-                expanded_diskmap[left_idx + i] = expanded_diskmap[right_idx - i];
-                expanded_diskmap[right_idx - i] = None;
+        for (id, fragments) in self.files.iter().enumerate() {
+            for span in fragments {
+                blocks[span.start..span.start + span.len].fill(Some(id));
             }
         }
 
-        right_idx -= file_size;
+        blocks
     }
 
-    expanded_diskmap
+    fn render(&self) -> String {
+        // Ids past 9 wrap around, which keeps one character per block.
+        self.blocks()
+            .iter()
+            .map(|block| match block {
+                Some(id) => char::from_digit((id % 10) as u32, 10).unwrap(),
+                None => '.',
+            })
+            .collect()
+    }
+
+    fn checksum(&self) -> usize {
+        self.files
+            .iter()
+            .enumerate()
+            .flat_map(|(id, fragments)| fragments.iter().map(move |span| span.checksum(id)))
+            .sum()
+    }
+}
+
+fn p1(input: &str) -> usize {
+    let mut disk = Disk::from(input);
+    disk.compact_blocks();
+
+    disk.checksum()
 }
 
 fn p2(input: &str) -> usize {
-    let diskmap = parse(input);
-    let expanded_diskmap = parse_diskmap(&diskmap);
-    let defragmented_diskmap = defragment_files(expanded_diskmap);
-
-    defragmented_diskmap
-        .iter()
-        .enumerate()
-        .map(|(i, a)| match a {
-            Some(v) => i * v,
-            None => 0,
-        })
-        .sum()
+    let mut disk = Disk::from(input);
+    disk.compact_files();
+
+    disk.checksum()
+}
+
+fn render(input: &str) -> String {
+    let mut disk = Disk::from(input);
+    let before = disk.render();
+
+    let mut blocks = disk.clone();
+    blocks.compact_blocks();
+
+    disk.compact_files();
+
+    format!("{before}\n{}\n{}", blocks.render(), disk.render())
 }
 
 fn main() {
@@ -156,6 +202,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "render" => println!("{}", render(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -163,6 +210,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::rng::Rng;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -175,4 +223,113 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 2858);
     }
+
+    #[test]
+    fn test_trailing_gap() {
+        let mut disk = Disk::from("75756");
+        disk.compact_blocks();
+
+        assert_eq!(disk.render(), "00000002222211111112..........");
+    }
+
+    #[test]
+    fn test_render() {
+        let mut disk = Disk::from(EXAMPLE);
+        assert_eq!(disk.render(), "00...111...2...333.44.5555.6666.777.888899");
+
+        let mut blocks = disk.clone();
+        blocks.compact_blocks();
+        assert_eq!(
+            blocks.render(),
+            "0099811188827773336446555566.............."
+        );
+
+        disk.compact_files();
+        assert_eq!(disk.render(), "00992111777.44.333....5555.6666.....8888..");
+    }
+
+    fn brute_force(diskmap: &str) -> (usize, usize) {
+        // Move single blocks, and then whole files, one at a time.
+        let original = Disk::from(diskmap).blocks();
+        let checksum = |blocks: &[Option<usize>]| -> usize {
+            blocks
+                .iter()
+                .enumerate()
+                .map(|(i, b)| i * b.unwrap_or(0))
+                .sum()
+        };
+
+        let mut blocks = original.clone();
+        let (mut i, mut j) = (0, blocks.len());
+        loop {
+            while i < j && blocks[i].is_some() {
+                i += 1;
+            }
+            while j > i && blocks[j - 1].is_none() {
+                j -= 1;
+            }
+            if i >= j {
+                break;
+            }
+            j -= 1;
+            blocks.swap(i, j);
+        }
+
+        let mut files = original;
+        for id in (0..diskmap.len().div_ceil(2)).rev() {
+            let Some(start) = files.iter().position(|&b| b == Some(id)) else {
+                continue;
+            };
+            let len = files.iter().filter(|&&b| b == Some(id)).count();
+
+            if let Some(gap) = files[..start]
+                .windows(len)
+                .position(|w| w.iter().all(|b| b.is_none()))
+            {
+                files[start..start + len].fill(None);
+                files[gap..gap + len].fill(Some(id));
+            }
+        }
+
+        (checksum(&blocks), checksum(&files))
+    }
+
+    fn random_diskmap(len: usize, seed: u64) -> String {
+        let mut rng = Rng::new(seed);
+        (0..len)
+            .map(|_| char::from_digit(rng.below(10) as u32, 10).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_brute_force() {
+        for seed in 1..=20 {
+            let diskmap = random_diskmap(200 + seed as usize, seed);
+
+            assert_eq!(brute_force(&diskmap), (p1(&diskmap), p2(&diskmap)));
+        }
+    }
+
+    #[test]
+    fn test_stress() {
+        // Too slow to brute force, so check that files are kept whole and
+        // never overlap or move right instead.
+        let diskmap = random_diskmap(100_000, 9);
+        let original = Disk::from(&diskmap);
+
+        let mut disk = original.clone();
+        disk.compact_files();
+
+        let mut seen = vec![false; disk.len];
+        for (before, after) in original.files.iter().zip(&disk.files) {
+            assert_eq!(after.len(), 1);
+            assert_eq!(after[0].len, before[0].len);
+            assert!(after[0].start <= before[0].start);
+
+            for block in &mut seen[after[0].start..after[0].start + after[0].len] {
+                assert!(!*block);
+                *block = true;
+            }
+        }
+    }
 }
@@ -1,5 +1,4 @@
-use cached::proc_macro::cached;
-use std::{env, fs};
+use std::{collections::HashMap, env, fs, iter::Sum, ops::AddAssign};
 
 fn parse(input: &str) -> Vec<usize> {
     input
@@ -31,25 +30,91 @@ fn split_stone_if_even(num: usize) -> Option<(usize, usize)> {
     Some((num / divisor, num % divisor))
 }
 
-#[cached]
-fn count(stone: usize, blink: usize) -> usize {
-    if blink == 0 {
-        1
-    } else if stone == 0 {
-        count(1, blink - 1)
+fn blink(stone: usize) -> (usize, Option<usize>) {
+    if stone == 0 {
+        (1, None)
     } else if let Some((first, second)) = split_stone_if_even(stone) {
-        count(first, blink - 1) + count(second, blink - 1)
+        (first, Some(second))
     } else {
-        count(stone * 2024, blink - 1)
+        (stone * 2024, None)
     }
 }
 
+// Stones are independent of each other and their order doesn't matter for
+// counting, so instead of tracking every stone we only track how many stones
+// of every value there are. The number of distinct values stays small, while
+// the counts grow exponentially, so they are generic over `C` to allow for
+// big integers.
+struct Stones<C> {
+    generation: usize,
+    distribution: HashMap<usize, C>,
+}
+
+impl<C> Stones<C>
+where
+    C: Clone + Default + From<u8> + for<'a> AddAssign<&'a C> + for<'a> Sum<&'a C>,
+{
+    fn new(stones: &[usize]) -> Stones<C> {
+        let mut distribution: HashMap<usize, C> = HashMap::new();
+
+        for &stone in stones {
+            *distribution.entry(stone).or_default() += &C::from(1);
+        }
+
+        Stones {
+            generation: 0,
+            distribution,
+        }
+    }
+
+    fn blink(&mut self) {
+        let mut next: HashMap<usize, C> = HashMap::with_capacity(self.distribution.len());
+
+        for (stone, count) in &self.distribution {
+            let (first, second) = blink(*stone);
+
+            *next.entry(first).or_default() += count;
+
+            if let Some(second) = second {
+                *next.entry(second).or_default() += count;
+            }
+        }
+
+        self.distribution = next;
+        self.generation += 1;
+    }
+
+    fn blink_n(&mut self, n: usize) -> Vec<usize> {
+        // Returns the number of distinct stone values after every blink.
+        (0..n)
+            .map(|_| {
+                self.blink();
+                self.distinct()
+            })
+            .collect()
+    }
+
+    fn distinct(&self) -> usize {
+        self.distribution.len()
+    }
+
+    fn count(&self) -> C {
+        self.distribution.values().sum()
+    }
+}
+
+fn count(input: &str, blinks: usize) -> u128 {
+    let mut stones = Stones::<u128>::new(&parse(input));
+    stones.blink_n(blinks);
+    stones.count()
+}
+
 fn p1(input: &str) -> usize {
-    parse(input).iter().map(|&s| count(s, 25)).sum()
+    count(input, 25) as usize
 }
 
 fn p2(input: &str) -> usize {
-    parse(input).iter().map(|&s| count(s, 75)).sum()
+    count(input, 75) as usize
 }
 
 fn main() {
@@ -70,6 +135,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use num_bigint::BigUint;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -82,4 +148,51 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 65601038650482);
     }
+
+    #[test]
+    fn test_matches_simulation() {
+        // Blinking at every stone one by one, for as long as that's feasible.
+        let mut stones = parse(EXAMPLE);
+
+        for blinks in 1..=25 {
+            stones = stones
+                .into_iter()
+                .flat_map(|stone| {
+                    let (first, second) = blink(stone);
+                    std::iter::once(first).chain(second)
+                })
+                .collect();
+
+            assert_eq!(count(EXAMPLE, blinks), stones.len() as u128);
+        }
+
+        // From the puzzle.
+        assert_eq!(count(EXAMPLE, 6), 22);
+    }
+
+    #[test]
+    fn test_distribution() {
+        let mut stones = Stones::<u128>::new(&parse(EXAMPLE));
+
+        // 125 17 -> 253000 1 7 -> 253 0 2024 14168 -> 512072 1 20 24 28676032
+        assert_eq!(stones.blink_n(3), vec![3, 4, 5]);
+        assert_eq!(stones.generation, 3);
+        assert_eq!(stones.distribution[&512072], 1);
+        assert_eq!(stones.count(), 5);
+    }
+
+    #[test]
+    fn test_many_blinks() {
+        let mut exact = Stones::<BigUint>::new(&parse(EXAMPLE));
+        let mut bounded = Stones::<u128>::new(&parse(EXAMPLE));
+
+        exact.blink_n(100);
+        bounded.blink_n(100);
+        assert_eq!(exact.count(), BigUint::from(bounded.count()));
+
+        // The number of distinct values levels off long before this.
+        let distinct = exact.blink_n(1900);
+        assert_eq!(distinct[1800..].iter().min(), distinct[1800..].iter().max());
+        assert!(exact.count() > BigUint::from(u128::MAX));
+    }
 }
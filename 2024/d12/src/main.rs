@@ -1,253 +1,156 @@
-use std::{collections::HashSet, env, fs};
-
-fn parse(input: &str) -> Vec<Vec<char>> {
-    input.lines().map(|l| l.trim().chars().collect()).collect()
+use std::{env, fs};
+use utils::union_find::UnionFind;
+
+struct Garden {
+    width: usize,
+    height: usize,
+    plants: Vec<char>,
+    // The region each plot belongs to, numbered in reading order.
+    labels: Vec<usize>,
 }
 
-fn get_contiguous(
-    pos: (usize, usize),
-    plot: &Vec<Vec<char>>,
-    grid_dim: (usize, usize),
-    mut contiguous_plot: HashSet<(usize, usize)>,
-) -> HashSet<(usize, usize)> {
-    if contiguous_plot.contains(&pos) {
-        return contiguous_plot;
-    }
-
-    contiguous_plot.insert(pos);
-
-    let (i, j) = pos;
-    let (h, w) = grid_dim;
-
-    if j > 0 && plot[i][j - 1] == plot[i][j] {
-        contiguous_plot = get_contiguous((i, j - 1), plot, grid_dim, contiguous_plot);
-    }
-
-    if i < h - 1 && plot[i + 1][j] == plot[i][j] {
-        contiguous_plot = get_contiguous((i + 1, j), plot, grid_dim, contiguous_plot);
-    }
-
-    if i > 0 && plot[i - 1][j] == plot[i][j] {
-        contiguous_plot = get_contiguous((i - 1, j), plot, grid_dim, contiguous_plot);
-    }
-
-    if j < w - 1 && plot[i][j + 1] == plot[i][j] {
-        contiguous_plot = get_contiguous((i, j + 1), plot, grid_dim, contiguous_plot);
-    }
-
-    contiguous_plot
+#[derive(Debug, PartialEq)]
+struct Region {
+    label: usize,
+    plant: char,
+    area: usize,
+    perimeter: usize,
+    sides: usize,
+    // The top left and bottom right plots of the region.
+    bounds: ((usize, usize), (usize, usize)),
 }
 
-fn count_perimeter(plot: &[Vec<char>], contiguous_plot: &HashSet<(usize, usize)>) -> usize {
-    let mut perimeter = 0;
+impl Garden {
+    fn from(input: &str) -> Self {
+        let rows: Vec<&str> = input.lines().map(|l| l.trim()).collect();
+        let (height, width) = (rows.len(), rows[0].len());
+        let plants: Vec<char> = rows.iter().flat_map(|row| row.chars()).collect();
 
-    let max_i = plot.len() - 1;
-    let max_j = plot[0].len() - 1;
+        // Joining each plot with the plots to its right and below is enough
+        // to connect every region.
+        let mut regions = UnionFind::new(plants.len());
 
-    for (i, j) in contiguous_plot {
-        if *j == 0 || plot[*i][j - 1] != plot[*i][*j] {
-            perimeter += 1;
-        }
-        if *i == max_i || plot[i + 1][*j] != plot[*i][*j] {
-            perimeter += 1;
-        }
-        if *i == 0 || plot[i - 1][*j] != plot[*i][*j] {
-            perimeter += 1;
+        for i in 0..plants.len() {
+            if i % width + 1 < width && plants[i] == plants[i + 1] {
+                regions.union(i, i + 1);
+            }
+            if i + width < plants.len() && plants[i] == plants[i + width] {
+                regions.union(i, i + width);
+            }
         }
-        if *j == max_j || plot[*i][j + 1] != plot[*i][*j] {
-            perimeter += 1;
+
+        let mut label_of_root = vec![None; plants.len()];
+        let mut n_labels = 0;
+
+        let labels = (0..plants.len())
+            .map(|i| {
+                let root = regions.find(i);
+                *label_of_root[root].get_or_insert_with(|| {
+                    n_labels += 1;
+                    n_labels - 1
+                })
+            })
+            .collect();
+
+        Garden {
+            width,
+            height,
+            plants,
+            labels,
         }
     }
 
-    perimeter
-}
-
-fn p1(input: &str) -> usize {
-    let plot = parse(input);
-
-    let grid_dim = (plot.len(), plot[0].len());
-
-    let mut visited_positions = HashSet::new();
-    let mut contiguous_plots: Vec<HashSet<(usize, usize)>> = Vec::new();
+    fn same_region(&self, i: usize, dr: isize, dc: isize) -> bool {
+        let row = (i / self.width).checked_add_signed(dr);
+        let col = (i % self.width).checked_add_signed(dc);
 
-    for i in 0..grid_dim.0 {
-        for j in 0..grid_dim.1 {
-            if visited_positions.contains(&(i, j)) {
-                continue;
+        match (row, col) {
+            (Some(row), Some(col)) if row < self.height && col < self.width => {
+                self.labels[row * self.width + col] == self.labels[i]
             }
-            let contiguous = get_contiguous((i, j), &plot, grid_dim, HashSet::new());
-
-            contiguous_plots.push(contiguous.clone());
-            visited_positions.extend(contiguous);
+            _ => false,
         }
     }
 
-    contiguous_plots
-        .iter()
-        .map(|c| count_perimeter(&plot, c) * c.len())
-        .sum()
-}
-
-fn traverse_straight(
-    pos: (usize, usize),
-    traversal_direction: char,
-    side_direction: char,
-    plot: &[Vec<char>],
-    grid_dim: (usize, usize),
-) -> HashSet<(usize, usize, char)> {
-    let (mut i, mut j) = pos;
-    let (h, w) = grid_dim;
-
-    let mut seen = HashSet::new();
-    seen.insert((i, j, side_direction));
-
-    match traversal_direction {
-        'l' => match side_direction {
-            'u' => {
-                while j > 0
-                    && plot[i][j - 1] == plot[i][j]
-                    && (i == 0 || plot[i - 1][j - 1] != plot[i][j])
-                {
-                    j -= 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            'd' => {
-                while j > 0
-                    && plot[i][j - 1] == plot[i][j]
-                    && (i == h - 1 || plot[i + 1][j - 1] != plot[i][j])
-                {
-                    j -= 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            _ => panic!("Invalid side direction"),
-        },
-        'd' => match side_direction {
-            'l' => {
-                while i < h - 1
-                    && plot[i + 1][j] == plot[i][j]
-                    && (j == 0 || plot[i + 1][j - 1] != plot[i][j])
-                {
-                    i += 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            'r' => {
-                while i < h - 1
-                    && plot[i + 1][j] == plot[i][j]
-                    && (j == w - 1 || plot[i + 1][j + 1] != plot[i][j])
-                {
-                    i += 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            _ => panic!("Invalid side direction"),
-        },
-        'u' => match side_direction {
-            'l' => {
-                while i > 0
-                    && plot[i - 1][j] == plot[i][j]
-                    && (j == 0 || plot[i - 1][j - 1] != plot[i][j])
-                {
-                    i -= 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            'r' => {
-                while i > 0
-                    && plot[i - 1][j] == plot[i][j]
-                    && (j == w - 1 || plot[i - 1][j + 1] != plot[i][j])
-                {
-                    i -= 1;
-                    seen.insert((i, j, side_direction));
-                }
+    fn regions(&self) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+
+        for (i, &label) in self.labels.iter().enumerate() {
+            let (row, col) = (i / self.width, i % self.width);
+
+            if label == regions.len() {
+                regions.push(Region {
+                    label,
+                    plant: self.plants[i],
+                    area: 0,
+                    perimeter: 0,
+                    sides: 0,
+                    bounds: ((row, col), (row, col)),
+                });
             }
-            _ => panic!("Invalid side direction"),
-        },
-        'r' => match side_direction {
-            'u' => {
-                while j < w - 1
-                    && plot[i][j + 1] == plot[i][j]
-                    && (i == 0 || plot[i - 1][j + 1] != plot[i][j])
-                {
-                    j += 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            'd' => {
-                while j < w - 1
-                    && plot[i][j + 1] == plot[i][j]
-                    && (i == h - 1 || plot[i + 1][j + 1] != plot[i][j])
-                {
-                    j += 1;
-                    seen.insert((i, j, side_direction));
-                }
-            }
-            _ => panic!("Invalid side direction"),
-        },
-        _ => panic!("Invalid traversal direction"),
-    }
-
-    seen
-}
-
-fn count_sides(plot: &[Vec<char>], contiguous_plot: &HashSet<(usize, usize)>) -> usize {
-    let max_i = plot.len();
-    let max_j = plot[0].len();
-
-    let mut sides: usize = 0;
-
-    let mut seen = HashSet::new();
 
-    for (i, j) in contiguous_plot {
-        if (*j == 0 || plot[*i][j - 1] != plot[*i][*j]) && !seen.contains(&(*i, *j, 'l')) {
-            sides += 1;
-            seen.extend(traverse_straight((*i, *j), 'u', 'l', plot, (max_i, max_j)));
-            seen.extend(traverse_straight((*i, *j), 'd', 'l', plot, (max_i, max_j)));
+            let region = &mut regions[label];
+            region.area += 1;
+
+            // Plots are visited top to bottom, so the first one sets the top.
+            let ((_, left), (bottom, right)) = &mut region.bounds;
+            *left = (*left).min(col);
+            *bottom = (*bottom).max(row);
+            *right = (*right).max(col);
+
+            region.perimeter += [(-1, 0), (0, 1), (1, 0), (0, -1)]
+                .iter()
+                .filter(|&&(dr, dc)| !self.same_region(i, dr, dc))
+                .count();
+
+            // A region has as many sides as corners. Each plot checks the
+            // corner it has in every diagonal direction: it is an outside
+            // corner if neither neighbour along that corner is in the region,
+            // and an inside one if both are but the diagonal plot isn't.
+            region.sides += [(-1, -1), (-1, 1), (1, 1), (1, -1)]
+                .iter()
+                .filter(|&&(dr, dc)| {
+                    let vertical = self.same_region(i, dr, 0);
+                    let horizontal = self.same_region(i, 0, dc);
+                    let diagonal = self.same_region(i, dr, dc);
+
+                    (!vertical && !horizontal) || (vertical && horizontal && !diagonal)
+                })
+                .count();
         }
-        if (*i == max_i - 1 || plot[i + 1][*j] != plot[*i][*j]) && !seen.contains(&(*i, *j, 'd')) {
-            sides += 1;
-            seen.extend(traverse_straight((*i, *j), 'r', 'd', plot, (max_i, max_j)));
-            seen.extend(traverse_straight((*i, *j), 'l', 'd', plot, (max_i, max_j)));
-        }
-        if (*i == 0 || plot[i - 1][*j] != plot[*i][*j]) && !seen.contains(&(*i, *j, 'u')) {
-            sides += 1;
-            seen.extend(traverse_straight((*i, *j), 'r', 'u', plot, (max_i, max_j)));
-            seen.extend(traverse_straight((*i, *j), 'l', 'u', plot, (max_i, max_j)));
-        }
-        if (*j == max_j - 1 || plot[*i][j + 1] != plot[*i][*j]) && !seen.contains(&(*i, *j, 'r')) {
-            sides += 1;
-            seen.extend(traverse_straight((*i, *j), 'u', 'r', plot, (max_i, max_j)));
-            seen.extend(traverse_straight((*i, *j), 'd', 'r', plot, (max_i, max_j)));
-        }
-    }
 
-    sides
+        regions
+    }
 }
-fn p2(input: &str) -> usize {
-    let plot = parse(input);
-
-    let grid_dim = (plot.len(), plot[0].len());
-
-    let mut visited_positions = HashSet::new();
-    let mut contiguous_plots: Vec<HashSet<(usize, usize)>> = Vec::new();
 
-    for i in 0..grid_dim.0 {
-        for j in 0..grid_dim.1 {
-            if visited_positions.contains(&(i, j)) {
-                continue;
-            }
-            let contiguous = get_contiguous((i, j), &plot, grid_dim, HashSet::new());
+fn report(input: &str) -> String {
+    Garden::from(input)
+        .regions()
+        .iter()
+        .map(|r| {
+            let ((top, left), (bottom, right)) = r.bounds;
+            format!(
+                "{} {}: area {}, perimeter {}, sides {}, rows {top}..={bottom}, columns {left}..={right}",
+                r.label, r.plant, r.area, r.perimeter, r.sides
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-            contiguous_plots.push(contiguous.clone());
-            visited_positions.extend(contiguous);
-        }
-    }
+fn p1(input: &str) -> usize {
+    Garden::from(input)
+        .regions()
+        .iter()
+        .map(|r| r.area * r.perimeter)
+        .sum()
+}
 
-    contiguous_plots
+fn p2(input: &str) -> usize {
+    Garden::from(input)
+        .regions()
         .iter()
-        .map(|c| count_sides(&plot, c) * c.len())
+        .map(|r| r.area * r.sides)
         .sum()
 }
 
@@ -262,6 +165,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "report" => println!("{}", report(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -291,4 +195,50 @@ mod tests {
         assert_eq!(p2(EXAMPLE4), 236);
         assert_eq!(p2(EXAMPLE5), 368);
     }
+
+    #[test]
+    fn test_holes() {
+        let regions = Garden::from("AAAA\nABBA\nABAA\nAAAA").regions();
+
+        assert_eq!(
+            regions,
+            [
+                Region {
+                    label: 0,
+                    plant: 'A',
+                    area: 13,
+                    perimeter: 24,
+                    sides: 10,
+                    bounds: ((0, 0), (3, 3)),
+                },
+                Region {
+                    label: 1,
+                    plant: 'B',
+                    area: 3,
+                    perimeter: 8,
+                    sides: 6,
+                    bounds: ((1, 1), (2, 2)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagonal() {
+        // Plots that only touch at a corner are separate regions, even when
+        // they have the same plant.
+        let regions = Garden::from("AB\nBA").regions();
+
+        assert_eq!(regions.len(), 4);
+        assert!(regions
+            .iter()
+            .all(|r| r.area == 1 && r.perimeter == 4 && r.sides == 4));
+
+        // The A region touches itself diagonally around the B in the middle.
+        let regions = Garden::from("AAB\nABA\nBAA").regions();
+
+        assert_eq!(regions.len(), 5);
+        assert_eq!((regions[0].area, regions[0].sides), (3, 6));
+        assert_eq!((regions[3].area, regions[3].sides), (3, 6));
+    }
 }
@@ -10,41 +10,151 @@ fn parse(input: &str) -> (Vec<String>, Vec<String>) {
     (available, designs)
 }
 
-fn all_possible(target: &str, words: &[String], cache: &mut HashMap<String, usize>) -> usize {
-    if target.is_empty() {
-        return 1;
+#[derive(Default)]
+struct Node {
+    children: HashMap<u8, usize>,
+    // Whether a towel ends at this node.
+    terminal: bool,
+}
+
+struct Trie {
+    nodes: Vec<Node>,
+}
+
+impl Trie {
+    fn new<S: AsRef<str>>(towels: &[S]) -> Self {
+        let mut trie = Trie {
+            nodes: vec![Node::default()],
+        };
+
+        for towel in towels {
+            trie.insert(towel.as_ref());
+        }
+
+        trie
+    }
+
+    fn insert(&mut self, towel: &str) {
+        let mut current = 0;
+
+        for &stripe in towel.as_bytes() {
+            current = match self.nodes[current].children.get(&stripe) {
+                Some(&next) => next,
+                None => {
+                    self.nodes.push(Node::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[current].children.insert(stripe, next);
+                    next
+                }
+            };
+        }
+
+        self.nodes[current].terminal = true;
     }
 
-    if let Some(&count) = cache.get(target) {
-        return count;
+    // The end of every towel that matches `design` starting at `start`.
+    fn matches<'a>(&'a self, design: &'a [u8], start: usize) -> impl Iterator<Item = usize> + 'a {
+        design[start..]
+            .iter()
+            .scan(0, |current, stripe| {
+                *current = *self.nodes[*current].children.get(stripe)?;
+                Some(*current)
+            })
+            .enumerate()
+            .filter(|&(_, node)| self.nodes[node].terminal)
+            .map(move |(i, _)| start + i + 1)
     }
+}
+
+struct Arrangements<'a> {
+    design: &'a str,
+    // ways[i] is the number of ways to make the first i stripes.
+    ways: Vec<u128>,
+    // The start of a towel ending at i, on a way to make the first i stripes.
+    previous: Vec<Option<usize>>,
+}
+
+impl<'a> Arrangements<'a> {
+    fn new(trie: &Trie, design: &'a str) -> Self {
+        let bytes = design.as_bytes();
 
-    let mut count = 0;
+        let mut ways = vec![0u128; bytes.len() + 1];
+        let mut previous = vec![None; bytes.len() + 1];
+        ways[0] = 1;
 
-    for word in words {
-        if target.starts_with(word) {
-            count += all_possible(&target[word.len()..], words, cache);
+        for start in 0..bytes.len() {
+            if ways[start] == 0 {
+                continue;
+            }
+
+            for end in trie.matches(bytes, start) {
+                ways[end] += ways[start];
+                previous[end].get_or_insert(start);
+            }
+        }
+
+        Arrangements {
+            design,
+            ways,
+            previous,
         }
     }
 
-    *cache.entry(target.to_string()).or_insert(count)
+    fn count(&self) -> u128 {
+        self.ways[self.design.len()]
+    }
+
+    fn example(&self) -> Option<Vec<&'a str>> {
+        if self.count() == 0 {
+            return None;
+        }
+
+        let mut towels = Vec::new();
+        let mut end = self.design.len();
+
+        while end > 0 {
+            let start = self.previous[end]?;
+            towels.push(&self.design[start..end]);
+            end = start;
+        }
+
+        towels.reverse();
+
+        Some(towels)
+    }
+}
+
+fn arrange(input: &str) -> String {
+    let (available_towels, designs) = parse(input);
+    let trie = Trie::new(&available_towels);
+
+    designs
+        .iter()
+        .map(|d| match Arrangements::new(&trie, d).example() {
+            Some(towels) => format!("{d}: {}", towels.join(" ")),
+            None => format!("{d}: impossible"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 fn p1(input: &str) -> usize {
     let (available_towels, designs) = parse(input);
+    let trie = Trie::new(&available_towels);
 
     designs
         .iter()
-        .filter(|d| all_possible(d, &available_towels, &mut HashMap::new()) > 0)
+        .filter(|d| Arrangements::new(&trie, d).count() > 0)
         .count()
 }
 
-fn p2(input: &str) -> usize {
+fn p2(input: &str) -> u128 {
     let (available_towels, designs) = parse(input);
+    let trie = Trie::new(&available_towels);
 
     designs
         .iter()
-        .map(|d| all_possible(d, &available_towels, &mut HashMap::new()))
+        .map(|d| Arrangements::new(&trie, d).count())
         .sum()
 }
 
@@ -59,6 +169,7 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "arrange" => println!("{}", arrange(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -66,6 +177,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::rng::Rng;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -78,4 +190,91 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 16);
     }
+
+    #[test]
+    fn test_example() {
+        let (available_towels, designs) = parse(EXAMPLE);
+        let trie = Trie::new(&available_towels);
+
+        for design in &designs {
+            let arrangements = Arrangements::new(&trie, design);
+
+            match arrangements.example() {
+                Some(towels) => {
+                    assert!(arrangements.count() > 0);
+                    assert_eq!(towels.concat(), *design);
+                    assert!(towels
+                        .iter()
+                        .all(|t| available_towels.iter().any(|a| a == t)));
+                }
+                None => assert_eq!(arrangements.count(), 0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_beyond_usize() {
+        // Covering n stripes with towels of length 1 and 2 has fib(n + 1) ways.
+        let trie = Trie::new(&["w", "ww"]);
+        let design = "w".repeat(150);
+
+        let (mut a, mut b) = (0u128, 1u128);
+        for _ in 0..150 {
+            (a, b) = (b, a + b);
+        }
+
+        assert!(b > usize::MAX as u128);
+        assert_eq!(Arrangements::new(&trie, &design).count(), b);
+    }
+
+    fn reference(design: &str, towels: &[String], cache: &mut HashMap<String, u128>) -> u128 {
+        if design.is_empty() {
+            return 1;
+        }
+
+        if let Some(&count) = cache.get(design) {
+            return count;
+        }
+
+        let count = towels
+            .iter()
+            .filter(|t| design.starts_with(t.as_str()))
+            .map(|t| reference(&design[t.len()..], towels, cache))
+            .sum();
+
+        *cache.entry(design.to_string()).or_insert(count)
+    }
+
+    #[test]
+    fn test_many_patterns() {
+        let mut rng = Rng::new(19);
+        let mut stripes =
+            |len: usize| -> String { (0..len).map(|_| rng.pick(b"wubrg") as char).collect() };
+
+        let mut towels: Vec<String> = (0..5000).map(|i| stripes(3 + i % 6)).collect();
+        towels.sort();
+        towels.dedup();
+
+        let trie = Trie::new(&towels);
+
+        for i in 0..50 {
+            // Build half the designs out of towels so that some are possible.
+            let design = if i % 2 == 0 {
+                (0..20)
+                    .map(|j| towels[(i * 97 + j * 31) % towels.len()].as_str())
+                    .collect()
+            } else {
+                stripes(100)
+            };
+
+            let arrangements = Arrangements::new(&trie, &design);
+            let expected = reference(&design, &towels, &mut HashMap::new());
+
+            assert_eq!(arrangements.count(), expected);
+            if i % 2 == 0 {
+                assert!(expected > 0);
+                assert_eq!(arrangements.example().unwrap().concat(), design);
+            }
+        }
+    }
 }
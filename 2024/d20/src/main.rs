@@ -1,147 +1,171 @@
-use std::{collections::HashMap, env, fs};
-
-type Maze = Vec<Vec<char>>;
-type Position = (usize, usize);
-type Direction = (i8, i8);
-type Path = Vec<Position>;
-
-fn parse(input: &str) -> Maze {
-    input
-        .lines()
-        .map(|line| line.trim().chars().collect())
-        .collect()
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env, fs,
+};
+
+struct Racetrack {
+    width: usize,
+    height: usize,
+    walls: Vec<bool>,
+    start: usize,
+    end: usize,
 }
 
-fn _render(maze: &Maze, history: &Path) {
-    for (i, row) in maze.iter().enumerate() {
-        for (j, cell) in row.iter().enumerate() {
-            if history.contains(&(i, j)) {
-                print!("\x1b[31m{}\x1b[0m", cell);
-            } else {
-                print!("{}", cell);
-            }
+impl Racetrack {
+    fn from(input: &str) -> Self {
+        let rows: Vec<&str> = input.lines().map(|line| line.trim()).collect();
+        let cells: Vec<char> = rows.iter().flat_map(|row| row.chars()).collect();
+
+        let find = |target| {
+            cells
+                .iter()
+                .position(|&c| c == target)
+                .unwrap_or_else(|| panic!("No {target} found"))
+        };
+
+        Racetrack {
+            width: rows[0].len(),
+            height: rows.len(),
+            walls: cells.iter().map(|&c| c == '#').collect(),
+            start: find('S'),
+            end: find('E'),
         }
-        println!();
     }
-    println!();
-}
 
-fn find_start(maze: &Maze) -> Position {
-    for (i, row) in maze.iter().enumerate() {
-        for (j, cell) in row.iter().enumerate() {
-            if *cell == 'S' {
-                return (i, j);
+    fn offset(&self, cell: usize, dr: isize, dc: isize) -> Option<usize> {
+        let row = (cell / self.width).checked_add_signed(dr)?;
+        let col = (cell % self.width).checked_add_signed(dc)?;
+
+        (row < self.height && col < self.width).then_some(row * self.width + col)
+    }
+
+    // The number of picoseconds it takes to get to each cell from `from`,
+    // without cheating. The track can branch, so this is a full BFS.
+    fn distances(&self, from: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.walls.len()];
+        let mut queue = VecDeque::from([from]);
+        distances[from] = Some(0);
+
+        while let Some(cell) = queue.pop_front() {
+            let d = distances[cell].unwrap();
+
+            for (dr, dc) in [(-1, 0), (0, 1), (1, 0), (0, -1)] {
+                if let Some(next) = self.offset(cell, dr, dc) {
+                    if !self.walls[next] && distances[next].is_none() {
+                        distances[next] = Some(d + 1);
+                        queue.push_back(next);
+                    }
+                }
             }
         }
-    }
 
-    panic!("No start found");
+        distances
+    }
 }
 
-fn adder(position: Position, vector: Direction) -> (i32, i32) {
-    (
-        position.0 as i32 + vector.0 as i32,
-        position.1 as i32 + vector.1 as i32,
-    )
+#[derive(Debug, Clone, Copy)]
+enum Metric {
+    Manhattan,
+    Chebyshev,
 }
 
-fn dist(a: Position, b: Position) -> usize {
-    ((a.0 as i32 - b.0 as i32).abs() + (a.1 as i32 - b.1 as i32).abs()) as usize
+impl Metric {
+    fn distance(&self, dr: isize, dc: isize) -> usize {
+        match self {
+            Metric::Manhattan => dr.unsigned_abs() + dc.unsigned_abs(),
+            Metric::Chebyshev => dr.unsigned_abs().max(dc.unsigned_abs()),
+        }
+    }
 }
 
-fn step(
-    maze: &Maze,
-    dimensions: Position,
-    position: Position,
-    direction: Direction,
-    mut path: Path,
-) -> Path {
-    path.push(position);
-
-    let (r_max, c_max) = dimensions;
+struct Cheat {
+    radius: usize,
+    metric: Metric,
+}
 
-    let (i, j) = position;
-    let (di, dj) = direction;
+impl Cheat {
+    fn new(radius: usize) -> Self {
+        Cheat {
+            radius,
+            metric: Metric::Manhattan,
+        }
+    }
 
-    if maze[i][j] == 'E' {
-        return path;
+    fn with_metric(self, metric: Metric) -> Self {
+        Cheat { metric, ..self }
     }
 
-    for (ndi, ndj) in [(di, dj), (dj, -di), (-dj, di)] {
-        let (ni, nj) = adder(position, (ndi, ndj));
+    // Every move a cheat can make, with how long it takes. For the Manhattan
+    // metric that's the diamond around the cell.
+    fn moves(&self) -> Vec<(isize, isize, usize)> {
+        let r = self.radius as isize;
 
-        if ni < 0 || nj < 0 {
-            continue;
-        }
+        (-r..=r)
+            .flat_map(|dr| (-r..=r).map(move |dc| (dr, dc)))
+            .map(|(dr, dc)| (dr, dc, self.metric.distance(dr, dc)))
+            .filter(|&(_, _, d)| d > 0 && d <= self.radius)
+            .collect()
+    }
 
-        let (ni, nj) = (ni as usize, nj as usize);
+    // How many cheats save each number of picoseconds. A cheat is known by
+    // where it starts and ends, and only counts if it saves time.
+    fn savings(&self, track: &Racetrack) -> BTreeMap<usize, usize> {
+        let from_start = track.distances(track.start);
+        let to_end = track.distances(track.end);
 
-        if ni > r_max || nj > c_max || maze[ni][nj] == '#' {
-            continue;
-        }
+        let fastest = from_start[track.end].expect("No route to the end");
+        let moves = self.moves();
 
-        return step(maze, dimensions, (ni, nj), (ndi, ndj), path);
-    }
+        let mut histogram = BTreeMap::new();
 
-    panic!("No solution found");
-}
+        for (cell, before) in from_start.iter().enumerate() {
+            let Some(before) = before else {
+                continue;
+            };
 
-fn cheats(solution: &Path, lasting: usize) -> HashMap<(Position, Position), usize> {
-    let mut cheat_savings = HashMap::new();
+            for &(dr, dc, d) in &moves {
+                let Some(after) = track.offset(cell, dr, dc).and_then(|c| to_end[c]) else {
+                    continue;
+                };
 
-    for s in 2..lasting + 1 {
-        for (i, &position) in solution.iter().enumerate().take(solution.len() - s) {
-            for (j, &other_position) in solution.iter().enumerate().skip(i + s) {
-                if dist(position, other_position) == s && j - i > s {
-                    cheat_savings
-                        .entry((position, other_position))
-                        .or_insert(j - i - s);
+                let time = before + d + after;
+                if time < fastest {
+                    *histogram.entry(fastest - time).or_insert(0) += 1;
                 }
             }
         }
-    }
 
-    cheat_savings
+        histogram
+    }
 }
 
-fn p1(input: &str, threshold: usize) -> usize {
-    let maze = parse(input);
-    let start = find_start(&maze);
-
-    let solution = step(
-        &maze,
-        (maze.len() - 1, maze[0].len() - 1),
-        start,
-        (-1, 0),
-        Vec::new(),
-    );
+fn histogram(input: &str, cheat: Cheat) -> String {
+    let track = Racetrack::from(input);
 
-    let cheat_savings = cheats(&solution, 2);
-
-    cheat_savings
+    cheat
+        .savings(&track)
         .iter()
-        .filter(|(_, &saved)| saved >= threshold)
-        .count()
+        .map(|(saved, n)| format!("{n} cheats save {saved} picoseconds"))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-fn p2(input: &str, threshold: usize) -> usize {
-    let maze = parse(input);
-    let start = find_start(&maze);
+fn count(input: &str, cheat: Cheat, threshold: usize) -> usize {
+    let track = Racetrack::from(input);
 
-    let solution = step(
-        &maze,
-        (maze.len() - 1, maze[0].len() - 1),
-        start,
-        (-1, 0),
-        Vec::new(),
-    );
+    cheat
+        .savings(&track)
+        .range(threshold..)
+        .map(|(_, n)| n)
+        .sum()
+}
 
-    let cheat_savings = cheats(&solution, 20);
+fn p1(input: &str, threshold: usize) -> usize {
+    count(input, Cheat::new(2), threshold)
+}
 
-    cheat_savings
-        .iter()
-        .filter(|(_, &saved)| saved >= threshold)
-        .count()
+fn p2(input: &str, threshold: usize) -> usize {
+    count(input, Cheat::new(20), threshold)
 }
 
 fn main() {
@@ -155,6 +179,20 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input, 100)),
         "p2" => println!("{}", p2(&input, 100)),
+        "histogram" => {
+            // Optionally followed by a radius and a metric.
+            let radius = args.get(3).map_or(2, |r| r.parse().unwrap());
+            let metric = match args.get(4).map(|m| m.as_str()) {
+                None | Some("manhattan") => Metric::Manhattan,
+                Some("chebyshev") => Metric::Chebyshev,
+                Some(other) => panic!("Invalid metric {other}"),
+            };
+
+            println!(
+                "{}",
+                histogram(&input, Cheat::new(radius).with_metric(metric))
+            )
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -174,4 +212,71 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE, 50), 285);
     }
+
+    #[test]
+    fn test_savings() {
+        let track = Racetrack::from(EXAMPLE);
+        let savings = Cheat::new(2).savings(&track);
+
+        assert_eq!(
+            savings.into_iter().collect::<Vec<_>>(),
+            [
+                (2, 14),
+                (4, 14),
+                (6, 2),
+                (8, 4),
+                (10, 2),
+                (12, 3),
+                (20, 1),
+                (36, 1),
+                (38, 1),
+                (40, 1),
+                (64, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_branching() {
+        // The fastest route goes around, past two dead ends that a cheat can
+        // join through the wall between them.
+        let track = Racetrack::from("#######\n#S.#.E#\n#.###.#\n#.....#\n#######");
+        let savings = Cheat::new(2).savings(&track);
+
+        assert_eq!(savings.into_iter().collect::<Vec<_>>(), [(4, 1)]);
+    }
+
+    #[test]
+    fn test_metrics() {
+        // Compare the scan against trying every pair of track cells.
+        let track = Racetrack::from(EXAMPLE);
+        let from_start = track.distances(track.start);
+        let to_end = track.distances(track.end);
+        let fastest = from_start[track.end].unwrap();
+
+        for metric in [Metric::Manhattan, Metric::Chebyshev] {
+            for radius in [2, 5, 20] {
+                let mut expected = BTreeMap::new();
+
+                for (a, before) in from_start.iter().enumerate() {
+                    for (b, after) in to_end.iter().enumerate() {
+                        let (Some(before), Some(after)) = (before, after) else {
+                            continue;
+                        };
+
+                        let dr = (b / track.width) as isize - (a / track.width) as isize;
+                        let dc = (b % track.width) as isize - (a % track.width) as isize;
+                        let d = metric.distance(dr, dc);
+
+                        if a != b && d <= radius && before + d + after < fastest {
+                            *expected.entry(fastest - before - d - after).or_insert(0) += 1;
+                        }
+                    }
+                }
+
+                let cheat = Cheat::new(radius).with_metric(metric);
+                assert_eq!(cheat.savings(&track), expected);
+            }
+        }
+    }
 }
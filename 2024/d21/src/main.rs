@@ -1,6 +1,7 @@
+use num_bigint::BigUint;
 use std::{
     cmp::Reverse,
-    collections::{BinaryHeap, HashMap},
+    collections::{BinaryHeap, HashMap, HashSet},
     env, fs, iter,
 };
 
@@ -14,54 +15,49 @@ struct Keypad {
     keys: HashMap<char, Position>,
     n_row: usize,
     n_col: usize,
-    nogo: Position,
+    gaps: HashSet<Position>,
 }
 
 impl Keypad {
-    fn numeric() -> Self {
-        /*
-        7 8 9
-        4 5 6
-        1 2 3
-          0 A
-        */
+    fn from_layout(layout: &str) -> Self {
+        // One character per button, with spaces marking the gaps the robot
+        // arms must never point at.
+        let mut keys = HashMap::new();
+        let mut gaps = HashSet::new();
+
+        let rows: Vec<&str> = layout.lines().collect();
+        let n_row = rows.len();
+        let n_col = rows.iter().map(|r| r.chars().count()).max().unwrap();
+
+        for (i, row) in rows.iter().enumerate() {
+            let row: Vec<char> = row.chars().collect();
+
+            for j in 0..n_col {
+                match row.get(j) {
+                    Some(' ') | None => {
+                        gaps.insert((i, j));
+                    }
+                    Some(&c) => {
+                        keys.insert(c, (i, j));
+                    }
+                }
+            }
+        }
+
         Keypad {
-            keys: HashMap::from([
-                ('7', (0, 0)),
-                ('8', (0, 1)),
-                ('9', (0, 2)),
-                ('4', (1, 0)),
-                ('5', (1, 1)),
-                ('6', (1, 2)),
-                ('1', (2, 0)),
-                ('2', (2, 1)),
-                ('3', (2, 2)),
-                ('0', (3, 1)),
-                ('A', (3, 2)),
-            ]),
-            n_row: 4,
-            n_col: 3,
-            nogo: (3, 0),
+            keys,
+            n_row,
+            n_col,
+            gaps,
         }
     }
 
+    fn numeric() -> Self {
+        Keypad::from_layout("789\n456\n123\n 0A")
+    }
+
     fn directional() -> Self {
-        /*
-          ^ A
-        < v >
-        */
-        Keypad {
-            keys: HashMap::from([
-                ('^', (0, 1)),
-                ('A', (0, 2)),
-                ('<', (1, 0)),
-                ('v', (1, 1)),
-                ('>', (1, 2)),
-            ]),
-            n_row: 2,
-            n_col: 3,
-            nogo: (0, 0),
-        }
+        Keypad::from_layout(" ^A\n<v>")
     }
 }
 
@@ -104,7 +100,7 @@ fn travel(keypad: &Keypad, current_char: char, target_char: char) -> Vec<String>
 
             let (ni, nj) = (ni as usize, nj as usize);
 
-            if ni >= keypad.n_row || nj >= keypad.n_col || (ni, nj) == keypad.nogo {
+            if ni >= keypad.n_row || nj >= keypad.n_col || keypad.gaps.contains(&(ni, nj)) {
                 continue;
             }
 
@@ -119,40 +115,121 @@ fn travel(keypad: &Keypad, current_char: char, target_char: char) -> Vec<String>
     paths
 }
 
-fn button_sequence(
-    code: &str,
-    keypad: &Keypad,
-    cache: &mut HashMap<(char, char, usize), usize>,
-    depth: usize,
-) -> usize {
-    if depth == 0 {
-        return code.len();
-    }
-
-    iter::once('A')
-        .chain(code.chars())
-        .collect::<Vec<char>>()
-        .windows(2)
-        .map(|w| {
-            let (current_char, next_char) = (w[0], w[1]);
-
-            if let Some(&len) = cache.get(&(current_char, next_char, depth)) {
-                len
-            } else {
-                let len = travel(keypad, current_char, next_char)
-                    .iter()
-                    .map(|path| button_sequence(path, &Keypad::directional(), cache, depth - 1))
-                    .min()
+struct Robots {
+    door: Keypad,
+    directional: Keypad,
+    n_robots: usize,
+    cache: HashMap<(char, char, usize), BigUint>,
+}
+
+impl Robots {
+    fn new(door: Keypad, n_robots: usize) -> Self {
+        // A robot types on the `door` keypad, and is controlled through
+        // `n_robots` directional keypads that are themselves operated by
+        // robots. We press the buttons on the last directional keypad.
+        Robots {
+            door,
+            directional: Keypad::directional(),
+            n_robots,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn keypad(&self, depth: usize) -> &Keypad {
+        if depth == self.n_robots + 1 {
+            &self.door
+        } else {
+            &self.directional
+        }
+    }
+
+    fn cost(&mut self, current_char: char, next_char: char, depth: usize) -> BigUint {
+        // Number of buttons we have to press to move the arm at `depth` from
+        // `current_char` to `next_char` and press it. Every arm above it
+        // starts and ends on 'A', so this doesn't depend on anything else.
+        if depth == 0 {
+            return BigUint::from(1u8);
+        }
+
+        if let Some(cost) = self.cache.get(&(current_char, next_char, depth)) {
+            return cost.clone();
+        }
+
+        let cost = travel(self.keypad(depth), current_char, next_char)
+            .iter()
+            .map(|path| self.sequence_cost(path, depth - 1))
+            .min()
+            .unwrap();
+
+        self.cache
+            .insert((current_char, next_char, depth), cost.clone());
+
+        cost
+    }
+
+    fn sequence_cost(&mut self, code: &str, depth: usize) -> BigUint {
+        iter::once('A')
+            .chain(code.chars())
+            .collect::<Vec<char>>()
+            .windows(2)
+            .map(|w| self.cost(w[0], w[1], depth))
+            .sum()
+    }
+
+    fn expand(&mut self, code: &str, depth: usize) -> String {
+        if depth == 0 {
+            return code.to_string();
+        }
+
+        iter::once('A')
+            .chain(code.chars())
+            .collect::<Vec<char>>()
+            .windows(2)
+            .map(|w| {
+                let path = travel(self.keypad(depth), w[0], w[1])
+                    .into_iter()
+                    .min_by_key(|path| self.sequence_cost(path, depth - 1))
                     .unwrap();
 
-                cache.insert((current_char, next_char, depth), len);
+                self.expand(&path, depth - 1)
+            })
+            .collect()
+    }
 
-                len
-            }
-        })
+    fn button_count(&mut self, code: &str) -> BigUint {
+        self.sequence_cost(code, self.n_robots + 1)
+    }
+
+    fn button_sequence(&mut self, code: &str) -> String {
+        // The actual buttons we have to press. Its length grows exponentially
+        // with the number of robots, so only use this for small chains.
+        self.expand(code, self.n_robots + 1)
+    }
+}
+
+fn complexity(input: &str, n_robots: usize) -> BigUint {
+    // The number of buttons grows exponentially with the number of robots,
+    // so it soon outgrows any fixed size integer.
+    let codes = parse(input);
+
+    let mut robots = Robots::new(Keypad::numeric(), n_robots);
+
+    codes
+        .iter()
+        .map(|code| robots.button_count(code) * get_numeric_part(code))
         .sum()
 }
 
+fn sequences(input: &str, n_robots: usize) -> String {
+    let mut robots = Robots::new(Keypad::numeric(), n_robots);
+
+    parse(input)
+        .iter()
+        .map(|code| format!("{code}: {}", robots.button_sequence(code)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn get_numeric_part(code: &str) -> usize {
     code.chars()
         .filter(|c| c.is_numeric())
@@ -162,29 +239,11 @@ fn get_numeric_part(code: &str) -> usize {
 }
 
 fn p1(input: &str) -> usize {
-    let codes = parse(input);
-
-    let mut cache = HashMap::new();
-
-    codes
-        .iter()
-        .map(|code| {
-            button_sequence(code, &Keypad::numeric(), &mut cache, 3) * get_numeric_part(code)
-        })
-        .sum()
+    complexity(input, 2).try_into().unwrap()
 }
 
 fn p2(input: &str) -> usize {
-    let codes = parse(input);
-
-    let mut cache = HashMap::new();
-
-    codes
-        .iter()
-        .map(|code| {
-            button_sequence(code, &Keypad::numeric(), &mut cache, 26) * get_numeric_part(code)
-        })
-        .sum()
+    complexity(input, 25).try_into().unwrap()
 }
 
 fn main() {
@@ -198,6 +257,11 @@ fn main() {
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
         "p2" => println!("{}", p2(&input)),
+        "sequence" => {
+            // Optionally followed by the number of robots, two by default.
+            let n_robots = args.get(3).map_or(2, |n| n.parse().unwrap());
+            println!("{}", sequences(&input, n_robots))
+        }
         _ => panic!("Invalid part"),
     };
 }
@@ -217,4 +281,42 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 154115708116294);
     }
+
+    #[test]
+    fn test_button_sequence() {
+        let mut robots = Robots::new(Keypad::numeric(), 2);
+
+        for code in parse(EXAMPLE) {
+            let sequence = robots.button_sequence(&code);
+
+            assert_eq!(BigUint::from(sequence.len()), robots.button_count(&code));
+        }
+
+        let mut robots = Robots::new(Keypad::numeric(), 0);
+        assert_eq!(robots.button_sequence("029A").len(), 12);
+    }
+
+    #[test]
+    fn test_custom_layout() {
+        // A keypad unlike either of the built in ones, with a gap in the
+        // middle that rules out most of the shortest routes.
+        let layout = "123\n4 5\n6A7";
+
+        let mut robots = Robots::new(Keypad::from_layout(layout), 0);
+        assert_eq!(robots.button_sequence("1A"), "<^^Avv>A");
+
+        let mut robots = Robots::new(Keypad::from_layout(layout), 1);
+        assert_eq!(robots.button_count("1A"), BigUint::from(18u8));
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        // The sequence grows roughly 2.5x with every robot, which is past what
+        // even a u128 can hold by 100 robots.
+        let mut robots = Robots::new(Keypad::numeric(), 100);
+        let count = robots.button_count("029A");
+
+        assert!(count > BigUint::from(u128::MAX));
+        assert!(count > complexity(EXAMPLE, 25));
+    }
 }
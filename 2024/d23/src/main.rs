@@ -1,94 +1,209 @@
-use std::{
-    collections::{BTreeSet, HashMap, HashSet},
-    env, fs,
-};
+use std::{collections::HashMap, env, fs};
+use utils::bitset::BitSet;
 
-fn parse(input: &str) -> HashMap<String, HashSet<String>> {
-    let mut network: HashMap<String, HashSet<String>> = HashMap::new();
+struct Network {
+    // Computers are interned, so the rest of the network only deals in ids.
+    names: Vec<String>,
+    adjacency: Vec<BitSet>,
+}
 
-    input.lines().for_each(|line| {
-        let connection: Vec<&str> = line.split('-').collect();
+fn parse(input: &str) -> Network {
+    let mut ids: HashMap<&str, usize> = HashMap::new();
+    let mut names = Vec::new();
+    let mut edges = Vec::new();
 
-        let (a, b) = (connection[0].to_string(), connection[1].to_string());
+    for line in input.lines() {
+        let (a, b) = line.split_once('-').unwrap();
 
-        network.entry(a.clone()).or_default().insert(b.clone());
-        network.entry(b).or_default().insert(a);
-    });
+        let [a, b] = [a, b].map(|name| {
+            *ids.entry(name).or_insert_with(|| {
+                names.push(name.to_string());
+                names.len() - 1
+            })
+        });
 
-    network
+        edges.push((a, b));
+    }
+
+    let mut adjacency = vec![BitSet::new(names.len()); names.len()];
+
+    for (a, b) in edges {
+        adjacency[a].insert(b);
+        adjacency[b].insert(a);
+    }
+
+    Network { names, adjacency }
 }
 
-fn get_triples(network: &HashMap<String, HashSet<String>>) -> HashSet<BTreeSet<String>> {
-    let mut triples = HashSet::new();
-
-    for (computer, connections) in network.iter() {
-        for (i, connection) in connections.iter().enumerate() {
-            for another_connection in connections.iter().skip(i) {
-                if network
-                    .get(connection)
-                    .unwrap()
-                    .contains(another_connection)
-                {
-                    let mut triple = BTreeSet::new();
-                    triple.insert(computer.clone());
-                    triple.insert((*connection).clone());
-                    triple.insert((*another_connection).clone());
-
-                    triples.insert(triple);
-                }
+impl Network {
+    fn all(&self) -> BitSet {
+        let mut all = BitSet::new(self.names.len());
+        for i in 0..self.names.len() {
+            all.insert(i);
+        }
+        all
+    }
+
+    fn k_cliques(&self, k: usize) -> Vec<Vec<usize>> {
+        // Every clique of exactly `k` computers, each listed once with its ids
+        // in increasing order.
+        let mut cliques = Vec::new();
+        self.extend_clique(&mut Vec::new(), self.all(), k, &mut cliques);
+        cliques
+    }
+
+    fn extend_clique(
+        &self,
+        clique: &mut Vec<usize>,
+        candidates: BitSet,
+        k: usize,
+        cliques: &mut Vec<Vec<usize>>,
+    ) {
+        if clique.len() == k {
+            cliques.push(clique.clone());
+            return;
+        }
+
+        for v in candidates.iter() {
+            // Only consider larger ids, so every clique is found only once.
+            let mut later = candidates.intersection(&self.adjacency[v]);
+            later.remove_up_to(v);
+
+            if later.len() + clique.len() + 1 >= k {
+                clique.push(v);
+                self.extend_clique(clique, later, k, cliques);
+                clique.pop();
             }
         }
     }
 
-    triples
-}
+    fn degeneracy_order(&self) -> Vec<usize> {
+        // Repeatedly take out the computer with the fewest connections left.
+        let n = self.names.len();
 
-fn p1(input: &str) -> usize {
-    let network = parse(input);
-    let triples = get_triples(&network);
+        let mut degree: Vec<usize> = self.adjacency.iter().map(|a| a.len()).collect();
+        let mut buckets: Vec<Vec<usize>> = vec![vec![]; n];
+        (0..n).for_each(|v| buckets[degree[v]].push(v));
 
-    triples
-        .iter()
-        .filter(|triple| triple.iter().any(|computer| computer.starts_with('t')))
-        .count()
-}
+        let mut removed = vec![false; n];
+        let mut order = Vec::with_capacity(n);
+        let mut d: usize = 0;
 
-fn get_largest_set(network: &HashMap<String, HashSet<String>>) -> HashSet<String> {
-    let mut largest_set = HashSet::new();
+        while order.len() < n {
+            d = d.saturating_sub(1);
 
-    for (computer, connections) in network.iter() {
-        let mut set = HashSet::new();
-        set.insert(computer.clone());
+            while buckets[d].is_empty() {
+                d += 1;
+            }
 
-        let mut candidates = connections.iter().collect::<Vec<&String>>();
+            let v = buckets[d].pop().unwrap();
 
-        while let Some(candidate) = candidates.pop() {
-            if set
-                .iter()
-                .all(|c| network.get(c).unwrap().contains(candidate))
-            {
-                set.insert(candidate.clone());
+            // Buckets may hold stale entries for computers whose degree has
+            // since dropped.
+            if removed[v] || degree[v] != d {
+                continue;
+            }
+
+            removed[v] = true;
+            order.push(v);
+
+            for u in self.adjacency[v].iter() {
+                if !removed[u] {
+                    degree[u] -= 1;
+                    buckets[degree[u]].push(u);
+                }
             }
         }
 
-        if set.len() > largest_set.len() {
-            largest_set = set;
+        order
+    }
+
+    fn bron_kerbosch(
+        &self,
+        r: &mut Vec<usize>,
+        mut p: BitSet,
+        mut x: BitSet,
+        best: &mut Vec<usize>,
+    ) {
+        if p.is_empty() && x.is_empty() {
+            if r.len() > best.len() {
+                *best = r.clone();
+            }
+            return;
+        }
+
+        if r.len() + p.len() <= best.len() {
+            return;
+        }
+
+        // Every maximal clique contains the pivot or one of its non-neighbours,
+        // so only those have to be tried.
+        let pivot = p
+            .iter()
+            .chain(x.iter())
+            .max_by_key(|&u| p.intersection(&self.adjacency[u]).len())
+            .unwrap();
+
+        for v in p.difference(&self.adjacency[pivot]).iter() {
+            r.push(v);
+            self.bron_kerbosch(
+                r,
+                p.intersection(&self.adjacency[v]),
+                x.intersection(&self.adjacency[v]),
+                best,
+            );
+            r.pop();
+
+            p.remove(v);
+            x.insert(v);
+        }
+    }
+
+    fn max_clique(&self) -> Vec<usize> {
+        // Bron–Kerbosch from every computer, in degeneracy order, only looking
+        // at the neighbours that come later. That keeps every search small.
+        let n = self.names.len();
+
+        let mut best = Vec::new();
+        let mut earlier = BitSet::new(n);
+
+        for v in self.degeneracy_order() {
+            let mut later = self.adjacency[v].difference(&earlier);
+            later.remove(v);
+
+            self.bron_kerbosch(
+                &mut vec![v],
+                later,
+                self.adjacency[v].intersection(&earlier),
+                &mut best,
+            );
+
+            earlier.insert(v);
         }
+
+        best
     }
 
-    largest_set
+    fn password(&self, clique: &[usize]) -> String {
+        let mut names: Vec<&str> = clique.iter().map(|&id| self.names[id].as_str()).collect();
+        names.sort_unstable();
+        names.join(",")
+    }
 }
 
-fn p2(input: &str) -> String {
+fn p1(input: &str) -> usize {
     let network = parse(input);
-    let largest_set = get_largest_set(&network);
 
-    {
-        let mut largest_set = largest_set.into_iter().collect::<Vec<String>>();
-        largest_set.sort_unstable();
-        largest_set
-    }
-    .join(",")
+    network
+        .k_cliques(3)
+        .iter()
+        .filter(|triple| triple.iter().any(|&c| network.names[c].starts_with('t')))
+        .count()
+}
+
+fn p2(input: &str) -> String {
+    let network = parse(input);
+    network.password(&network.max_clique())
 }
 
 fn main() {
@@ -109,6 +224,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::rng::Rng;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -121,4 +237,56 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), "co,de,ka,ta");
     }
+
+    #[test]
+    fn test_k_cliques() {
+        // Every pair in a set of 6 computers is connected.
+        let names = ["a", "b", "c", "d", "e", "f"];
+        let input: Vec<String> = (0..6)
+            .flat_map(|i| (i + 1..6).map(move |j| format!("{}-{}", names[i], names[j])))
+            .collect();
+        let network = parse(&input.join("\n"));
+
+        assert_eq!(network.k_cliques(2).len(), 15);
+        assert_eq!(network.k_cliques(4).len(), 15);
+        assert_eq!(network.k_cliques(6).len(), 1);
+        assert_eq!(network.k_cliques(7).len(), 0);
+        assert_eq!(network.password(&network.max_clique()), "a,b,c,d,e,f");
+    }
+
+    #[test]
+    fn test_large_network() {
+        // 5000 computers with random sparse connections, and a planted clique
+        // of 20 computers that is much larger than anything random.
+        let n = 5000;
+        let mut rng = Rng::new(2024);
+
+        let name = |i: usize| format!("c{i:04}");
+
+        let mut connections: Vec<String> = (0..n * 10)
+            .map(|_| (rng.below(n), rng.below(n)))
+            .filter(|(a, b)| a != b)
+            .map(|(a, b)| format!("{}-{}", name(a), name(b)))
+            .collect();
+
+        let planted: Vec<usize> = (0..20).map(|i| i * 241 + 7).collect();
+        for (i, &a) in planted.iter().enumerate() {
+            for &b in &planted[i + 1..] {
+                connections.push(format!("{}-{}", name(a), name(b)));
+            }
+        }
+
+        let network = parse(&connections.join("\n"));
+
+        let expected: Vec<String> = planted.iter().map(|&i| name(i)).collect();
+        assert_eq!(network.password(&network.max_clique()), expected.join(","));
+
+        // Every 3-clique is made of three connections.
+        let triangles = network.k_cliques(3);
+        assert!(triangles.len() >= 1140);
+        let connected = |a: usize, b: usize| network.adjacency[a].iter().any(|c| c == b);
+        assert!(triangles
+            .iter()
+            .all(|t| connected(t[0], t[1]) && connected(t[0], t[2]) && connected(t[1], t[2])));
+    }
 }
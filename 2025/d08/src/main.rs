@@ -1,12 +1,12 @@
-use itertools::Itertools;
 use std::{
-    collections::{BTreeSet, HashMap},
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
     env, fs,
 };
+use utils::union_find::UnionFind;
 
 type Point = (usize, usize, usize);
-type Circuit<'a> = BTreeSet<&'a Point>;
-type Circuits<'a> = HashMap<usize, Circuit<'a>>;
+type Cell = (i64, i64, i64);
 
 fn parse(input: &str) -> Vec<Point> {
     input
@@ -19,105 +19,169 @@ fn parse(input: &str) -> Vec<Point> {
         .collect()
 }
 
-fn distance(p: &Point, q: &Point) -> f64 {
+fn distance(p: &Point, q: &Point) -> u64 {
     // fn that is a proxy for euclidean distance. Taking the sqrt of the output would be
     // the euclidean distance.
-    let d1 = (p.0 as f64 - q.0 as f64).powi(2);
-    let d2 = (p.1 as f64 - q.1 as f64).powi(2);
-    let d3 = (p.2 as f64 - q.2 as f64).powi(2);
+    let d1 = p.0.abs_diff(q.0).pow(2);
+    let d2 = p.1.abs_diff(q.1).pow(2);
+    let d3 = p.2.abs_diff(q.2).pow(2);
 
-    d1 + d2 + d3
+    (d1 + d2 + d3) as u64
 }
 
-fn get_closest_sorted_pairs(points: &[Point]) -> Vec<(&Point, &Point)> {
-    // fn to take all possible pairs and sort them in ascending order of distance between them
-    points
-        .iter()
-        .tuple_combinations() // take all possible pairs of points
-        .map(|(p, q)| (p, q, distance(p, q))) // calculate the pair's distance as the 3rd element
-        .sorted_unstable_by(|a, b| a.2.partial_cmp(&b.2).unwrap()) // sort pair by distance
-        .map(|(p, q, _)| (p, q)) // drop distance
-        .collect()
+struct Neighbours {
+    // Points with a larger index than the point we're looking around, found so
+    // far, closest first.
+    found: BinaryHeap<Reverse<(u64, usize)>>,
+    // Every cell up to this (Chebyshev) distance from the point's cell has
+    // been searched.
+    ring: i64,
 }
 
-fn init_circuits(points: &'_ [Point]) -> Circuits<'_> {
-    // In the beginning of time, all points will be in their own circuits
-    points
-        .iter()
-        .enumerate() // index will be used as the circuit_id
-        .map(|(i, point)| (i, BTreeSet::from([point]))) // (circuit_id, `Circuit`)
-        .collect() // collect as a HashMap where key = circuit_id and value is the `Circuit`
+// Generates all pairs of points in ascending order of distance, without
+// materialising all O(n²) of them. Points are bucketed into a grid of cubic
+// cells. Around every point, cells are searched in growing rings: once every
+// cell up to ring r is searched, no unseen point can be closer than r cells,
+// so any point found that is closer than that is safe to hand out. Merging the
+// per point streams through a heap gives the global order.
+struct ClosestPairs<'a> {
+    points: &'a [Point],
+    cell_size: usize,
+    grid: HashMap<Cell, Vec<usize>>,
+    max_ring: i64,
+    neighbours: Vec<Neighbours>,
+    queue: BinaryHeap<Reverse<(u64, usize, usize)>>,
 }
 
-fn get_circuit_id(point: &Point, circuits: &Circuits) -> usize {
-    for (&circuit_id, circuit) in circuits.iter() {
-        if circuit.contains(point) {
-            return circuit_id;
+impl<'a> ClosestPairs<'a> {
+    fn new(points: &'a [Point]) -> ClosestPairs<'a> {
+        let extent = points
+            .iter()
+            .flat_map(|&(x, y, z)| [x, y, z])
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        // Aim for a couple of points per cell.
+        let cell_size = ((extent.pow(3) / points.len().max(1)) as f64).cbrt().ceil() as usize;
+        let cell_size = cell_size.max(1);
+
+        let mut grid: HashMap<Cell, Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            grid.entry(ClosestPairs::cell(p, cell_size))
+                .or_default()
+                .push(i);
         }
-    }
 
-    unreachable!("Should have found circuit by now");
-}
+        let mut pairs = ClosestPairs {
+            points,
+            cell_size,
+            grid,
+            max_ring: extent.div_ceil(cell_size) as i64,
+            neighbours: (0..points.len())
+                .map(|_| Neighbours {
+                    found: BinaryHeap::new(),
+                    ring: -1,
+                })
+                .collect(),
+            queue: BinaryHeap::new(),
+        };
+
+        for i in 0..points.len() {
+            pairs.enqueue_next(i);
+        }
 
-fn connect<'a>(pair: (&'a Point, &'a Point), mut circuits: Circuits<'a>) -> Circuits<'a> {
-    // fn to take a pair of points and connect their respective circuits together
-    // functionally, q's circuit is absorbed by p's circuit.
-    let (p, q) = pair;
+        pairs
+    }
 
-    let cid_p = get_circuit_id(p, &circuits);
-    let cid_q = get_circuit_id(q, &circuits);
+    fn cell(&(x, y, z): &Point, cell_size: usize) -> Cell {
+        (
+            (x / cell_size) as i64,
+            (y / cell_size) as i64,
+            (z / cell_size) as i64,
+        )
+    }
 
-    if cid_p == cid_q {
-        return circuits;
+    fn search_ring(&mut self, i: usize) {
+        let n = &mut self.neighbours[i];
+        n.ring += 1;
+        let r = n.ring;
+
+        let (cx, cy, cz) = ClosestPairs::cell(&self.points[i], self.cell_size);
+
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    if dx.abs().max(dy.abs()).max(dz.abs()) != r {
+                        continue;
+                    }
+
+                    let Some(cell) = self.grid.get(&(cx + dx, cy + dy, cz + dz)) else {
+                        continue;
+                    };
+
+                    for &j in cell.iter().filter(|&&j| j > i) {
+                        let d = distance(&self.points[i], &self.points[j]);
+                        n.found.push(Reverse((d, j)));
+                    }
+                }
+            }
+        }
     }
 
-    let q_circuit = circuits.remove(&cid_q).unwrap();
-    let p_circuit = circuits.get_mut(&cid_p).unwrap();
+    fn enqueue_next(&mut self, i: usize) {
+        // Push the next closest neighbour of `i` to the queue, if any.
+        loop {
+            let n = &self.neighbours[i];
+            let safe = (n.ring.max(0) as u64 * self.cell_size as u64).pow(2);
+
+            match n.found.peek() {
+                Some(&Reverse((d, _))) if d <= safe || n.ring >= self.max_ring => {
+                    let Reverse((d, j)) = self.neighbours[i].found.pop().unwrap();
+                    self.queue.push(Reverse((d, i, j)));
+                    return;
+                }
+                None if n.ring >= self.max_ring => return,
+                _ => self.search_ring(i),
+            }
+        }
+    }
+}
 
-    p_circuit.extend(q_circuit);
+impl Iterator for ClosestPairs<'_> {
+    type Item = (usize, usize);
 
-    circuits
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let Reverse((_, i, j)) = self.queue.pop()?;
+        self.enqueue_next(i);
+        Some((i, j))
+    }
 }
 
 fn p1(input: &str, n: usize) -> usize {
     let points: Vec<Point> = parse(input);
 
-    let pairs = get_closest_sorted_pairs(&points).into_iter().take(n);
-
-    let mut circuits = init_circuits(&points);
+    let mut circuits = UnionFind::new(points.len());
 
-    for pair in pairs {
-        circuits = connect(pair, circuits);
+    for (i, j) in ClosestPairs::new(&points).take(n) {
+        circuits.union(i, j);
     }
 
-    circuits
-        .values()
-        .map(|circuit| circuit.len())
-        .sorted_unstable_by(|l1, l2| l1.cmp(l2).reverse())
-        .take(3)
-        .product()
+    circuits.sizes().iter().take(3).product()
 }
 
 fn p2(input: &str) -> usize {
+    // Kruskal's algorithm: the last connection of the minimum spanning tree
+    // is the one that joins everything into a single circuit.
     let points: Vec<Point> = parse(input);
 
-    let pairs = get_closest_sorted_pairs(&points);
+    let mut circuits = UnionFind::new(points.len());
 
-    let mut circuits = init_circuits(&points);
+    let (i, j) = ClosestPairs::new(&points)
+        .find(|&(i, j)| circuits.union(i, j) && circuits.n_sets() == 1)
+        .unwrap();
 
-    let mut idx: Option<usize> = None;
-    for (i, pair) in pairs.iter().enumerate() {
-        circuits = connect(*pair, circuits);
-
-        if circuits.len() == 1 {
-            idx = Some(i);
-            break;
-        }
-    }
-
-    let (p, q) = pairs.get(idx.unwrap()).unwrap();
-
-    p.0 * q.0
+    points[i].0 * points[j].0
 }
 
 fn main() {
@@ -138,6 +202,7 @@ fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use utils::rng::Rng;
 
     const EXAMPLE: &str = include_str!("../eg1.txt");
 
@@ -150,4 +215,45 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 25272);
     }
+
+    fn random_points(n: usize, max: usize, seed: u64) -> Vec<Point> {
+        let mut rng = Rng::new(seed);
+        (0..n)
+            .map(|_| (rng.below(max), rng.below(max), rng.below(max)))
+            .collect()
+    }
+
+    #[test]
+    fn test_closest_pairs() {
+        // Compare against sorting all pairs, like the solution used to.
+        for (n, max, seed) in [(300, 1000, 1), (200, 20, 2), (50, 100_000, 3)] {
+            let points = random_points(n, max, seed);
+
+            let mut expected: Vec<u64> = (0..n)
+                .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                .map(|(i, j)| distance(&points[i], &points[j]))
+                .collect();
+            expected.sort_unstable();
+
+            let generated: Vec<u64> = ClosestPairs::new(&points)
+                .map(|(i, j)| distance(&points[i], &points[j]))
+                .collect();
+
+            assert_eq!(generated, expected);
+        }
+    }
+
+    #[test]
+    fn test_circuits() {
+        let points = parse(EXAMPLE);
+        let mut circuits = UnionFind::new(points.len());
+
+        for (i, j) in ClosestPairs::new(&points).take(10) {
+            circuits.union(i, j);
+        }
+
+        // One circuit of 5, one of 4, two of 2 and 7 single junction boxes.
+        assert_eq!(circuits.n_sets(), 11);
+        assert_eq!(circuits.sizes()[..5], [5, 4, 2, 2, 1]);
+    }
 }
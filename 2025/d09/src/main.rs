@@ -61,71 +61,92 @@ fn get_edge(point_a: &Point, point_b: &Point) -> HashSet<Point> {
     }
 }
 
-fn get_edges(corner_a: &Point, corner_b: &Point) -> HashSet<Point> {
-    // Get all the `Point`s of a rectangle, given by two adjacent corners
-    // `corner_a` and `corner_b`.
+fn fill_shape(compressed_red_tiles: &[Point]) -> Vec<Vec<bool>> {
+    // Mark every tile of the compressed grid that lies on or inside the loop.
+    // The grid is padded by one tile on every side, so flood filling from the
+    // top left corner reaches everything outside the loop, and whatever is left
+    // unreached must be part of the shape.
+    let width = compressed_red_tiles.iter().map(|(x, _)| x).max().unwrap() + 3;
+    let height = compressed_red_tiles.iter().map(|(_, y)| y).max().unwrap() + 3;
+
+    let boundary: HashSet<_> = rasterize(compressed_red_tiles)
+        .into_iter()
+        .map(|(x, y)| (x + 1, y + 1))
+        .collect();
 
-    let (x_a, y_a) = corner_a;
-    let (x_b, y_b) = corner_b;
+    let mut outside = vec![vec![false; width]; height];
+    let mut to_visit = vec![(0, 0)];
 
-    let corner_c = (*x_a, *y_b);
-    let corner_d = (*x_b, *y_a);
+    while let Some((x, y)) = to_visit.pop() {
+        if outside[y][x] || boundary.contains(&(x, y)) {
+            continue;
+        }
 
-    get_edge(corner_a, &corner_c)
-        .into_iter()
-        .chain(get_edge(corner_a, &corner_d))
-        .chain(get_edge(corner_b, &corner_c))
-        .chain(get_edge(corner_b, &corner_d))
+        outside[y][x] = true;
+
+        to_visit.extend(
+            [
+                (x.wrapping_sub(1), y),
+                (x, y + 1),
+                (x, y.wrapping_sub(1)),
+                (x + 1, y),
+            ]
+            .into_iter()
+            .filter(|&(x, y)| x < width && y < height),
+        );
+    }
+
+    outside[1..height - 1]
+        .iter()
+        .map(|row| row[1..width - 1].iter().map(|o| !o).collect())
         .collect()
 }
-fn flood_fill(point: &Point, rasterized_shape: &HashSet<Point>) -> HashSet<Point> {
-    let mut to_visit = vec![*point];
-    let mut filled = HashSet::new();
 
-    while let Some((x, y)) = to_visit.pop() {
-        if filled.contains(&(x, y)) || rasterized_shape.contains(&(x, y)) {
-            continue;
-        }
+fn prefix_sums(shape: &[Vec<bool>]) -> Vec<Vec<usize>> {
+    // sums[y][x] holds the number of shape tiles in the rectangle spanned by
+    // (0, 0) inclusive and (x, y) exclusive.
+    let height = shape.len();
+    let width = shape[0].len();
 
-        filled.insert((x, y));
+    let mut sums = vec![vec![0; width + 1]; height + 1];
 
-        to_visit.extend([
-            (x.saturating_sub(1), y),
-            (x, y + 1),
-            (x, y.saturating_sub(1)),
-            (x + 1, y),
-        ]);
+    for y in 0..height {
+        for x in 0..width {
+            sums[y + 1][x + 1] =
+                sums[y][x + 1] + sums[y + 1][x] - sums[y][x] + usize::from(shape[y][x]);
+        }
     }
 
-    filled
+    sums
+}
+
+fn is_inside(sums: &[Vec<usize>], (x_a, y_a): &Point, (x_b, y_b): &Point) -> bool {
+    // A rectangle is inside the shape iff every one of its tiles is.
+    let (x1, x2) = (*x_a.min(x_b), *x_a.max(x_b) + 1);
+    let (y1, y2) = (*y_a.min(y_b), *y_a.max(y_b) + 1);
+
+    let count = sums[y2][x2] + sums[y1][x1] - sums[y1][x2] - sums[y2][x1];
+
+    count == (x2 - x1) * (y2 - y1)
 }
+
 fn find_best_rectangle(
-    compressed_shape: &HashSet<Point>,
+    sums: &[Vec<usize>],
     compressed_red_tiles: &[Point],
     compressed_to_original_map: &HashMap<&Point, Point>,
 ) -> usize {
-    let mut best_known_area: Option<usize> = None;
-
-    for (corner_a, corner_b) in compressed_red_tiles.iter().tuple_combinations() {
-        let area = area(
-            compressed_to_original_map.get(corner_a).unwrap(),
-            compressed_to_original_map.get(corner_b).unwrap(),
-        );
-
-        if let Some(best_area) = best_known_area {
-            if area > best_area
-                && get_edges(corner_a, corner_b)
-                    .iter()
-                    .all(|p| compressed_shape.contains(p))
-            {
-                best_known_area = Some(area);
-            }
-        } else {
-            best_known_area = Some(area);
-        }
-    }
-
-    best_known_area.unwrap_or(0)
+    compressed_red_tiles
+        .iter()
+        .tuple_combinations()
+        .filter(|(corner_a, corner_b)| is_inside(sums, corner_a, corner_b))
+        .map(|(corner_a, corner_b)| {
+            area(
+                compressed_to_original_map.get(corner_a).unwrap(),
+                compressed_to_original_map.get(corner_b).unwrap(),
+            )
+        })
+        .max()
+        .unwrap_or(0)
 }
 
 fn compress_coordinates(points: &[Point]) -> Vec<Point> {
@@ -143,7 +164,7 @@ fn compress_coordinates(points: &[Point]) -> Vec<Point> {
         .collect()
 }
 
-fn p2(input: &str, seed: Point) -> usize {
+fn p2(input: &str) -> usize {
     let red_tiles = parse(input);
 
     let compressed_red_tiles = compress_coordinates(&red_tiles);
@@ -151,18 +172,9 @@ fn p2(input: &str, seed: Point) -> usize {
     let compressed_to_original_map: HashMap<_, _> =
         compressed_red_tiles.iter().zip(red_tiles).collect();
 
-    let compressed_rasterized_shape = rasterize(&compressed_red_tiles);
-
-    let compressed_shape: HashSet<_> = flood_fill(&seed, &compressed_rasterized_shape)
-        .into_iter()
-        .chain(compressed_rasterized_shape)
-        .collect();
+    let sums = prefix_sums(&fill_shape(&compressed_red_tiles));
 
-    find_best_rectangle(
-        &compressed_shape,
-        &compressed_red_tiles,
-        &compressed_to_original_map,
-    )
+    find_best_rectangle(&sums, &compressed_red_tiles, &compressed_to_original_map)
 }
 
 fn main() {
@@ -175,7 +187,7 @@ fn main() {
 
     match part.as_str() {
         "p1" => println!("{}", p1(&input)),
-        "p2" => println!("{}", p2(&input, (250, 300))),
+        "p2" => println!("{}", p2(&input)),
         _ => panic!("Invalid part"),
     };
 }
@@ -193,6 +205,14 @@ mod tests {
 
     #[test]
     fn test_p2() {
-        assert_eq!(p2(EXAMPLE, (5, 3)), 24);
+        assert_eq!(p2(EXAMPLE), 24);
+    }
+
+    #[test]
+    fn test_p2_concave() {
+        // A U shape, where the full bounding box would span the notch.
+        let input = "1,1\n3,1\n3,5\n5,5\n5,1\n7,1\n7,7\n1,7";
+
+        assert_eq!(p2(input), 21);
     }
 }
@@ -1,6 +1,5 @@
 use rayon::prelude::*;
 use std::{env, fs};
-use z3::{ast::Int, Optimize, SatResult::Sat};
 
 type IntVec = Vec<u64>;
 
@@ -55,7 +54,222 @@ fn parse(input: &str) -> Vec<MachineSpec> {
         .collect()
 }
 
+// Solve the light toggling over GF(2). Every button is a column and every light
+// a row, packed into a bitmask with the target bit stored at position `m`. After
+// elimination, every assignment of the free variables fixes the pivot variables,
+// so we just enumerate the free ones and keep the assignment with the fewest
+// presses.
+fn solve_lights(target: &[u64], buttons: &[IntVec]) -> Option<usize> {
+    let m = buttons.len();
+    let n = target.len();
+
+    let mut rows: Vec<u64> = (0..n)
+        .map(|j| {
+            let coefficients = (0..m)
+                .filter(|&i| buttons[i][j] % 2 == 1)
+                .fold(0, |row, i| row | (1 << i));
+
+            coefficients | ((target[j] % 2) << m)
+        })
+        .collect();
+
+    let mut pivots = Vec::new();
+
+    for col in 0..m {
+        let rank = pivots.len();
+
+        let Some(pivot) = (rank..n).find(|&r| rows[r] >> col & 1 == 1) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+
+        for r in 0..n {
+            if r != rank && rows[r] >> col & 1 == 1 {
+                rows[r] ^= rows[rank];
+            }
+        }
+
+        pivots.push(col);
+    }
+
+    // A row with no coefficients left but a target bit set reads 0 = 1.
+    if rows[pivots.len()..].iter().any(|&row| row != 0) {
+        return None;
+    }
+
+    let free: Vec<usize> = (0..m).filter(|col| !pivots.contains(col)).collect();
+
+    (0..(1u64 << free.len()))
+        .map(|choice| {
+            let presses = free
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| choice >> k & 1 == 1)
+                .fold(0u64, |presses, (_, &col)| presses | (1 << col));
+
+            let presses = pivots
+                .iter()
+                .enumerate()
+                .fold(presses, |presses, (r, &col)| {
+                    let parity = (rows[r] >> m & 1) ^ ((rows[r] & presses).count_ones() as u64 & 1);
+                    presses | (parity << col)
+                });
+
+            presses.count_ones() as usize
+        })
+        .min()
+}
+
+// Solve the joltages over the non-negative integers. The system is brought into
+// reduced row echelon form using fraction-free elimination (rows are kept small
+// by dividing out their gcd), after which every pivot variable is an affine
+// function of the free variables. The free variables are then searched within
+// their natural bounds: a button can't be pressed more often than the smallest
+// joltage it contributes to.
+fn solve_joltages(target: &[u64], buttons: &[IntVec]) -> Option<usize> {
+    let m = buttons.len();
+    let n = target.len();
+
+    let mut rows: Vec<Vec<i64>> = (0..n)
+        .map(|j| {
+            (0..m)
+                .map(|i| buttons[i][j] as i64)
+                .chain([target[j] as i64])
+                .collect()
+        })
+        .collect();
+
+    let mut pivots = Vec::new();
+
+    for col in 0..m {
+        let rank = pivots.len();
+
+        let Some(pivot) = (rank..n).find(|&r| rows[r][col] != 0) else {
+            continue;
+        };
+        rows.swap(rank, pivot);
+
+        for r in 0..n {
+            if r == rank || rows[r][col] == 0 {
+                continue;
+            }
+
+            let pivot_row = rows[rank].clone();
+            let p = pivot_row[col];
+            let q = rows[r][col];
+
+            for (x, y) in rows[r].iter_mut().zip(&pivot_row) {
+                *x = *x * p - y * q;
+            }
+
+            normalize(&mut rows[r]);
+        }
+
+        pivots.push(col);
+    }
+
+    // A row with no coefficients left but a non-zero target reads 0 = c.
+    if rows[pivots.len()..].iter().any(|row| row[m] != 0) {
+        return None;
+    }
+
+    let bounds: Vec<i64> = buttons
+        .iter()
+        .map(|b| {
+            (0..n)
+                .filter(|&j| b[j] > 0)
+                .map(|j| target[j] as i64)
+                .min()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let free: Vec<usize> = (0..m).filter(|col| !pivots.contains(col)).collect();
+
+    let mut best = None;
+    let mut presses = vec![0; free.len()];
+
+    loop {
+        let free_total: i64 = presses.iter().sum();
+
+        let pivot_total = pivots
+            .iter()
+            .enumerate()
+            .map(|(r, &col)| {
+                let rhs = rows[r][m]
+                    - free
+                        .iter()
+                        .zip(&presses)
+                        .map(|(&f, x)| rows[r][f] * x)
+                        .sum::<i64>();
+
+                let p = rows[r][col];
+
+                (rhs % p == 0 && rhs / p >= 0).then_some(rhs / p)
+            })
+            .sum::<Option<i64>>();
+
+        if let Some(pivot_total) = pivot_total {
+            let total = (free_total + pivot_total) as usize;
+            best = Some(best.map_or(total, |b: usize| b.min(total)));
+        }
+
+        // Advance the free variables like an odometer.
+        let Some(k) = (0..free.len()).find(|&k| presses[k] < bounds[free[k]]) else {
+            break;
+        };
+
+        presses[k] += 1;
+        presses[..k].fill(0);
+    }
+
+    best
+}
+
+// Divide a row by the gcd of its entries and make its leading entry positive.
+fn normalize(row: &mut [i64]) {
+    let g = row.iter().fold(0, |g, &x| gcd(g, x.abs()));
+
+    if g == 0 {
+        return;
+    }
+
+    let sign = match row.iter().find(|&&x| x != 0) {
+        Some(&x) if x < 0 => -1,
+        _ => 1,
+    };
+
+    row.iter_mut().for_each(|x| *x = *x / g * sign);
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
 fn optimize(target: &[u64], buttons: &[IntVec], joltage_switch: bool) -> usize {
+    // for p1, a button would only toggle the light. So, if the button was
+    // pressed an even number of times, it will be the same as the light's
+    // starting state. If pressed an odd number of times, the light will be
+    // toggled from its initial state.
+    // for p2, every button press counts towards increasing the joltage
+    let solution = if joltage_switch {
+        solve_joltages(target, buttons)
+    } else {
+        solve_lights(target, buttons)
+    };
+
+    solution.expect("All problems should be solvable")
+}
+
+// The original z3 formulation, kept around to cross-check the native solver.
+#[cfg(feature = "z3")]
+fn optimize_z3(target: &[u64], buttons: &[IntVec], joltage_switch: bool) -> usize {
+    use z3::{ast::Int, Optimize, SatResult::Sat};
+
     // Buttons represented as a binary vector of 0s and 1s.
     let buttons: Vec<Vec<Int>> = buttons
         .iter()
@@ -91,11 +305,6 @@ fn optimize(target: &[u64], buttons: &[IntVec], joltage_switch: bool) -> usize {
         let terms: Vec<_> = (0..m).map(|i| &X[i] * &buttons[i][j]).collect();
         let sum = Int::add(&terms.iter().collect::<Vec<_>>());
 
-        // for p1, a button would only toggle the light. So, if the button was
-        // pressed an even number of times, it will be the same as the light's
-        // starting state. If pressed an odd number of times, the light will be
-        // toggled from its initial state.
-        // for p2, every button press counts towards increasing the joltage
         let t = if joltage_switch { sum } else { sum.rem(2) };
 
         optimizer.assert(&t.eq(&target[j]));
@@ -168,4 +377,28 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE), 33);
     }
+
+    #[test]
+    fn test_solve_inconsistent() {
+        // The only button toggles both lights, so they can't end up different.
+        let buttons = vec![vec![1, 1]];
+
+        assert_eq!(solve_lights(&[1, 0], &buttons), None);
+        assert_eq!(solve_joltages(&[2, 1], &buttons), None);
+    }
+
+    #[cfg(feature = "z3")]
+    #[test]
+    fn test_matches_z3() {
+        for m in parse(EXAMPLE) {
+            assert_eq!(
+                optimize(&m.indicator_lights, &m.buttons, false),
+                optimize_z3(&m.indicator_lights, &m.buttons, false)
+            );
+            assert_eq!(
+                optimize(&m.joltages, &m.buttons, true),
+                optimize_z3(&m.joltages, &m.buttons, true)
+            );
+        }
+    }
 }
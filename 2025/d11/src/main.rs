@@ -1,5 +1,3 @@
-#[cfg_attr(test, allow(unused_imports))]
-use cached::proc_macro::cached;
 use std::{
     collections::{HashMap, HashSet},
     env, fs,
@@ -25,47 +23,148 @@ fn parse(input: &str) -> Connections {
         .collect()
 }
 
-// NOTE: The cache is global. Therefore, during testing the cache is polluted by the other part's
-// solution, for both parts. So it is conditionally disabled for `test` compilation.
-#[cfg_attr(
-    not(test),
-    cached(key = "String", convert = r#"{ format!("{}-{}", from, to) }"#)
-)]
-fn count_paths(from: &str, to: &str, connections: &Connections) -> usize {
-    if from == to {
-        return 1;
+fn topological_order<'a>(
+    start: &'a str,
+    connections: &'a Connections,
+) -> Result<Vec<&'a str>, String> {
+    // Kahn's algorithm, over the devices reachable from `start`.
+    let mut reachable: HashSet<&str> = HashSet::from([start]);
+    let mut to_visit = vec![start];
+
+    while let Some(device) = to_visit.pop() {
+        for output in connections.get(device).into_iter().flatten() {
+            if reachable.insert(output) {
+                to_visit.push(output);
+            }
+        }
     }
 
-    if from == "out" {
-        return 0;
+    let mut n_inputs: HashMap<&str, usize> = reachable.iter().map(|&d| (d, 0)).collect();
+    for &device in &reachable {
+        for output in connections.get(device).into_iter().flatten() {
+            *n_inputs.get_mut(output.as_str()).unwrap() += 1;
+        }
     }
 
-    connections
-        .get(from)
-        .unwrap()
+    let mut ready: Vec<&str> = n_inputs
         .iter()
-        .map(|d| count_paths(d, to, connections))
-        .sum()
+        .filter(|(_, n)| **n == 0)
+        .map(|(&d, _)| d)
+        .collect();
+    let mut order = Vec::with_capacity(reachable.len());
+
+    while let Some(device) = ready.pop() {
+        order.push(device);
+
+        for output in connections.get(device).into_iter().flatten() {
+            let n = n_inputs.get_mut(output.as_str()).unwrap();
+            *n -= 1;
+
+            if *n == 0 {
+                ready.push(output);
+            }
+        }
+    }
+
+    if order.len() < reachable.len() {
+        // What's left is on a cycle or after one. Peeling off the devices
+        // that don't lead anywhere within it leaves just the cycles.
+        let mut stuck: HashSet<&str> = n_inputs
+            .into_iter()
+            .filter(|(_, n)| *n > 0)
+            .map(|(d, _)| d)
+            .collect();
+
+        let leads_into = |device: &str, stuck: &HashSet<&str>| {
+            connections
+                .get(device)
+                .into_iter()
+                .flatten()
+                .any(|o| stuck.contains(o.as_str()))
+        };
+
+        while let Some(&device) = stuck.iter().find(|&&d| !leads_into(d, &stuck)) {
+            stuck.remove(device);
+        }
+
+        let mut stuck: Vec<&str> = stuck.into_iter().collect();
+        stuck.sort_unstable();
+
+        return Err(format!(
+            "The devices reachable from {start} contain a cycle, involving {:?}",
+            stuck
+        ));
+    }
+
+    Ok(order)
+}
+
+fn count_paths(
+    connections: &Connections,
+    start: &str,
+    end: &str,
+    waypoints: &[&str],
+) -> Result<usize, String> {
+    // Number of paths from `start` to `end` that visit every waypoint, in any
+    // order. Going through the devices in topological order, we keep track of
+    // the number of paths reaching each device for every set of waypoints
+    // visited along the way (as a bitmask).
+    // A waypoint listed twice still only has to be visited once.
+    let mut waypoints = waypoints.to_vec();
+    waypoints.sort_unstable();
+    waypoints.dedup();
+
+    if waypoints.len() > 16 {
+        return Err(format!("Too many waypoints: {}", waypoints.len()));
+    }
+
+    let order = topological_order(start, connections)?;
+
+    let bit = |device: &str| {
+        waypoints
+            .iter()
+            .position(|&w| w == device)
+            .map_or(0, |i| 1 << i)
+    };
+
+    let all = (1 << waypoints.len()) - 1;
+
+    let mut paths: HashMap<&str, Vec<usize>> = HashMap::new();
+    paths.entry(start).or_insert(vec![0; all + 1])[bit(start)] = 1;
+
+    for device in order {
+        let Some(counts) = paths.remove(device) else {
+            continue;
+        };
+
+        if device == end {
+            return Ok(counts[all]);
+        }
+
+        for output in connections.get(device).into_iter().flatten() {
+            let output_bit = bit(output);
+            let output_counts = paths.entry(output).or_insert(vec![0; all + 1]);
+
+            for (visited, &n) in counts.iter().enumerate() {
+                output_counts[visited | output_bit] += n;
+            }
+        }
+    }
+
+    Ok(0)
 }
 
 fn p1(input: &str) -> usize {
     let connections = parse(input);
-    count_paths("you", "out", &connections)
+
+    count_paths(&connections, "you", "out", &[]).unwrap_or_else(|reason| panic!("{reason}"))
 }
 
 fn p2(input: &str) -> usize {
     let connections = parse(input);
 
-    let num_svr_fft_dac_out = count_paths("svr", "fft", &connections)
-        * count_paths("fft", "dac", &connections)
-        * count_paths("dac", "out", &connections);
-    let num_svr_dac_fft_out = count_paths("svr", "dac", &connections)
-        * count_paths("dac", "fft", &connections)
-        * count_paths("fft", "out", &connections);
-
-    // NOTE: Since the input is a DAG, one of these terms will be 0.
-    // (it is the second one (dac -> fft) in my case)
-    num_svr_fft_dac_out + num_svr_dac_fft_out
+    count_paths(&connections, "svr", "out", &["fft", "dac"])
+        .unwrap_or_else(|reason| panic!("{reason}"))
 }
 
 fn main() {
@@ -99,4 +198,45 @@ mod tests {
     fn test_p2() {
         assert_eq!(p2(EXAMPLE2), 2);
     }
+
+    #[test]
+    fn test_waypoints() {
+        let connections = parse(EXAMPLE2);
+        let count =
+            |waypoints: &[&str]| count_paths(&connections, "svr", "out", waypoints).unwrap();
+
+        assert_eq!(count(&[]), 8);
+        assert_eq!(count(&["fft"]), 4);
+        assert_eq!(count(&["dac", "fft"]), 2);
+        assert_eq!(count(&["fft", "dac", "hub"]), 0);
+        assert_eq!(count(&["fft", "dac", "ggg"]), 1);
+        assert_eq!(count(&["tty", "eee", "fff", "hhh"]), 1);
+        assert_eq!(count(&["unknown"]), 0);
+    }
+
+    #[test]
+    fn test_repeated_waypoints() {
+        let connections = parse(EXAMPLE2);
+        let count =
+            |waypoints: &[&str]| count_paths(&connections, "svr", "out", waypoints).unwrap();
+
+        assert_eq!(count(&["fft", "fft"]), 4);
+        assert_eq!(count(&["dac", "fft", "dac"]), 2);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let connections = parse("you: aaa\naaa: bbb out\nbbb: aaa\nccc: ccc");
+
+        assert_eq!(
+            count_paths(&connections, "you", "out", &[]),
+            Err(
+                "The devices reachable from you contain a cycle, involving [\"aaa\", \"bbb\"]"
+                    .to_string()
+            )
+        );
+
+        // The cycle isn't reachable from here.
+        assert_eq!(count_paths(&connections, "out", "out", &[]), Ok(1));
+    }
 }
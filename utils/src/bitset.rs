@@ -0,0 +1,116 @@
+// A fixed size set of the integers 0..n, packed 64 to a word.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new(n: usize) -> BitSet {
+        BitSet {
+            words: vec![0; n.div_ceil(64)],
+        }
+    }
+
+    pub fn insert(&mut self, i: usize) -> bool {
+        // Returns whether `i` wasn't in the set yet.
+        let missing = !self.contains(i);
+        self.words[i / 64] |= 1 << (i % 64);
+        missing
+    }
+
+    pub fn remove(&mut self, i: usize) {
+        self.words[i / 64] &= !(1 << (i % 64));
+    }
+
+    pub fn contains(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub fn remove_up_to(&mut self, i: usize) {
+        // Removes everything up to and including `i`.
+        self.words[..i / 64].fill(0);
+        self.words[i / 64] &= !(u64::MAX >> (63 - i % 64));
+    }
+
+    pub fn union_with(&mut self, other: &BitSet) {
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+    }
+
+    pub fn intersection(&self, other: &BitSet) -> BitSet {
+        BitSet {
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| a & b)
+                .collect(),
+        }
+    }
+
+    pub fn difference(&self, other: &BitSet) -> BitSet {
+        BitSet {
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| a & !b)
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    i * 64 + bit
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitset() {
+        let mut a = BitSet::new(130);
+        let mut b = BitSet::new(130);
+
+        assert!(a.insert(3));
+        assert!(!a.insert(3));
+        a.insert(64);
+        a.insert(129);
+        b.insert(64);
+        b.insert(100);
+
+        assert!(a.contains(129) && !a.contains(100));
+        assert_eq!(a.intersection(&b).iter().collect::<Vec<_>>(), [64]);
+        assert_eq!(a.difference(&b).iter().collect::<Vec<_>>(), [3, 129]);
+
+        a.union_with(&b);
+        assert_eq!(a.len(), 4);
+
+        a.remove_up_to(64);
+        assert_eq!(a.iter().collect::<Vec<_>>(), [100, 129]);
+
+        a.remove(100);
+        a.remove(129);
+        assert!(a.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+// Simple polygons on the integer lattice, given as their vertices in order.
+// The last vertex connects back to the first.
+pub type Point = (i64, i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Containment {
+    Inside,
+    Boundary,
+    Outside,
+}
+
+fn edges(vertices: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(&a, &b)| (a, b))
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+pub fn vertices<I>(instructions: I) -> Vec<Point>
+where
+    I: IntoIterator<Item = (Direction, i64)>,
+{
+    // Follows the instructions from the origin, with y growing downwards.
+    let mut current = (0, 0);
+
+    instructions
+        .into_iter()
+        .map(|(direction, distance)| {
+            let (x, y) = current;
+
+            current = match direction {
+                Direction::Up => (x, y - distance),
+                Direction::Down => (x, y + distance),
+                Direction::Left => (x - distance, y),
+                Direction::Right => (x + distance, y),
+            };
+
+            current
+        })
+        .collect()
+}
+
+pub fn double_area(vertices: &[Point]) -> u64 {
+    // Shoelace formula. The area of a lattice polygon can be a half, so this
+    // is twice the area to stay an integer.
+    edges(vertices)
+        .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
+        .sum::<i64>()
+        .unsigned_abs()
+}
+
+pub fn boundary_points(vertices: &[Point]) -> u64 {
+    // An edge from a to b passes through gcd(|dx|, |dy|) lattice points,
+    // counting a but not b.
+    edges(vertices)
+        .map(|((x1, y1), (x2, y2))| gcd(x1.abs_diff(x2), y1.abs_diff(y2)))
+        .sum()
+}
+
+pub fn interior_points(vertices: &[Point]) -> Option<u64> {
+    // Pick's theorem: A = i + b / 2 - 1. It only holds for polygons that
+    // enclose some area, so empty, single point or collinear vertices (and
+    // self intersecting ones, that can break the count) give None.
+    let double_area = double_area(vertices);
+
+    if double_area == 0 {
+        return None;
+    }
+
+    Some((double_area + 2).checked_sub(boundary_points(vertices))? / 2)
+}
+
+pub fn lattice_points(vertices: &[Point]) -> Option<u64> {
+    Some(interior_points(vertices)? + boundary_points(vertices))
+}
+
+pub fn contains(vertices: &[Point], point: Point) -> Containment {
+    // Winding number, counting the edges that cross the horizontal line
+    // through `point` to its right, upwards positively and downwards
+    // negatively.
+    let (px, py) = point;
+    let mut winding = 0;
+
+    for ((x1, y1), (x2, y2)) in edges(vertices) {
+        // Where `point` is relative to the line through the edge.
+        let side = (x2 - x1) * (py - y1) - (px - x1) * (y2 - y1);
+
+        let within = x1.min(x2) <= px && px <= x1.max(x2) && y1.min(y2) <= py && py <= y1.max(y2);
+        if side == 0 && within {
+            return Containment::Boundary;
+        }
+
+        if y1 <= py && py < y2 && side > 0 {
+            winding += 1;
+        } else if y2 <= py && py < y1 && side < 0 {
+            winding -= 1;
+        }
+    }
+
+    if winding == 0 {
+        Containment::Outside
+    } else {
+        Containment::Inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rectangle() {
+        let rectangle = vertices([
+            (Direction::Right, 4),
+            (Direction::Down, 3),
+            (Direction::Left, 4),
+            (Direction::Up, 3),
+        ]);
+
+        assert_eq!(rectangle, [(4, 0), (4, 3), (0, 3), (0, 0)]);
+        assert_eq!(double_area(&rectangle), 24);
+        assert_eq!(boundary_points(&rectangle), 14);
+        assert_eq!(interior_points(&rectangle), Some(6));
+        assert_eq!(lattice_points(&rectangle), Some(20));
+    }
+
+    #[test]
+    fn test_triangle() {
+        // The diagonal edge passes through (2, 1) and (4, 2).
+        let triangle = [(0, 0), (6, 3), (0, 3)];
+
+        assert_eq!(double_area(&triangle), 18);
+        assert_eq!(boundary_points(&triangle), 12);
+        assert_eq!(interior_points(&triangle), Some(4));
+    }
+
+    #[test]
+    fn test_degenerate() {
+        assert_eq!(interior_points(&[]), None);
+        assert_eq!(lattice_points(&[]), None);
+        assert_eq!(interior_points(&[(2, 3)]), None);
+
+        // Collinear vertices have 8 boundary points but no area.
+        let line = [(0, 0), (2, 0), (4, 0)];
+        assert_eq!(boundary_points(&line), 8);
+        assert_eq!(interior_points(&line), None);
+        assert_eq!(lattice_points(&line), None);
+    }
+
+    #[test]
+    fn test_contains() {
+        // A U shape, wound either way.
+        let mut u = vec![
+            (0, 0),
+            (2, 0),
+            (2, 4),
+            (4, 4),
+            (4, 0),
+            (6, 0),
+            (6, 6),
+            (0, 6),
+        ];
+
+        for _ in 0..2 {
+            assert_eq!(contains(&u, (1, 1)), Containment::Inside);
+            assert_eq!(contains(&u, (3, 5)), Containment::Inside);
+            assert_eq!(contains(&u, (5, 2)), Containment::Inside);
+
+            assert_eq!(contains(&u, (2, 2)), Containment::Boundary);
+            assert_eq!(contains(&u, (3, 4)), Containment::Boundary);
+            assert_eq!(contains(&u, (6, 6)), Containment::Boundary);
+
+            assert_eq!(contains(&u, (3, 2)), Containment::Outside);
+            assert_eq!(contains(&u, (3, 0)), Containment::Outside);
+            assert_eq!(contains(&u, (7, 4)), Containment::Outside);
+
+            u.reverse();
+        }
+
+        let square = [(0, 0), (4, 0), (4, 4), (0, 4)];
+        assert_eq!(contains(&square, (2, 2)), Containment::Inside);
+        assert_eq!(contains(&square, (2, 0)), Containment::Boundary);
+        assert_eq!(contains(&square, (5, 0)), Containment::Outside);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod bitset;
+pub mod geometry;
+// Only for tests, which enable it as a dev-dependency feature.
+#[cfg(any(test, feature = "rng"))]
+pub mod rng;
+pub mod union_find;
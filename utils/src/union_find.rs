@@ -0,0 +1,123 @@
+// Disjoint sets over the elements 0..n, with union by rank and path
+// compression, so both `find` and `union` are practically constant time.
+#[derive(Debug, Clone)]
+pub struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+    size: Vec<usize>,
+    n_sets: usize,
+}
+
+impl UnionFind {
+    pub fn new(n: usize) -> UnionFind {
+        UnionFind {
+            parent: (0..n).collect(),
+            rank: vec![0; n],
+            size: vec![1; n],
+            n_sets: n,
+        }
+    }
+
+    pub fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+
+        // Point everything along the way directly at the root.
+        let mut x = x;
+        while self.parent[x] != root {
+            let next = self.parent[x];
+            self.parent[x] = root;
+            x = next;
+        }
+
+        root
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        // Returns whether `a` and `b` were in different sets.
+        let (a, b) = (self.find(a), self.find(b));
+
+        if a == b {
+            return false;
+        }
+
+        let (small, large) = if self.rank[a] < self.rank[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        self.parent[small] = large;
+        self.size[large] += self.size[small];
+
+        if self.rank[small] == self.rank[large] {
+            self.rank[large] += 1;
+        }
+
+        self.n_sets -= 1;
+
+        true
+    }
+
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub fn size(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+
+    pub fn n_sets(&self) -> usize {
+        self.n_sets
+    }
+
+    pub fn sizes(&self) -> Vec<usize> {
+        // Sizes of all the sets, largest first.
+        let mut sizes: Vec<usize> = (0..self.parent.len())
+            .filter(|&x| self.parent[x] == x)
+            .map(|x| self.size[x])
+            .collect();
+
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        sizes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union() {
+        let mut uf = UnionFind::new(6);
+
+        assert!(uf.union(0, 1));
+        assert!(uf.union(2, 3));
+        assert!(uf.union(1, 3));
+        assert!(!uf.union(0, 2));
+
+        assert!(uf.connected(0, 3));
+        assert!(!uf.connected(0, 4));
+        assert_eq!(uf.size(2), 4);
+        assert_eq!(uf.n_sets(), 3);
+        assert_eq!(uf.sizes(), vec![4, 1, 1]);
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let n = 100_000;
+        let mut uf = UnionFind::new(n);
+
+        for i in 1..n {
+            uf.union(i - 1, i);
+        }
+
+        assert_eq!(uf.n_sets(), 1);
+        assert_eq!(uf.size(0), n);
+        assert_eq!(uf.find(0), uf.find(n - 1));
+    }
+}